@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Walk up from `start` to the nearest directory holding `.scuttle/scuttle.db`, like git finds `.git`.
+pub fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .find(|dir| dir.join(".scuttle").join("scuttle.db").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Per-repo settings live next to the DB but are never pushed.
+pub fn get_repo_config_path() -> PathBuf {
+    PathBuf::from(".scuttle/config.json")
+}
+
+pub fn load_repo_config() -> Result<serde_json::Value> {
+    let path = get_repo_config_path();
+    if !path.exists() {
+        return Ok(serde_json::json!({}));
+    }
+    let data = fs::read_to_string(&path).context("Failed to read repo config")?;
+    serde_json::from_str(&data).context("Failed to parse repo config")
+}
+
+pub fn save_repo_config(config: &serde_json::Value) -> Result<()> {
+    let data = serde_json::to_string_pretty(config).context("Failed to serialize repo config")?;
+    fs::write(get_repo_config_path(), data).context("Failed to write repo config")
+}
+
+/// The remote this repo pushes to and pulls from when none is given.
+pub fn get_default_remote() -> Result<Option<String>> {
+    let config = load_repo_config()?;
+    Ok(config.get("default_remote").and_then(|r| r.as_str()).map(|s| s.to_string()))
+}
+
+/// Where this repo lives on `remote_name`, e.g. `{"root_id": "...", "root_name": "..."}`.
+pub fn get_remote_binding(remote_name: &str) -> Result<Option<serde_json::Value>> {
+    let config = load_repo_config()?;
+    Ok(config.get("remotes").and_then(|r| r.get(remote_name)).cloned())
+}
+
+/// Record the remote root of this repo, optionally making it the default remote.
+pub fn set_remote_binding(remote_name: &str, root_id: &str, root_name: &str, make_default: bool) -> Result<()> {
+    let mut config = load_repo_config()?;
+    if !config["remotes"].is_object() {
+        config["remotes"] = serde_json::json!({});
+    }
+    let binding = &mut config["remotes"][remote_name];
+    if !binding.is_object() {
+        *binding = serde_json::json!({});
+    }
+    binding["root_id"] = serde_json::json!(root_id);
+    binding["root_name"] = serde_json::json!(root_name);
+    if make_default || config.get("default_remote").is_none() {
+        config["default_remote"] = serde_json::json!(remote_name);
+    }
+    save_repo_config(&config)
+}
+
+/// Copy of the remote DB as of the last successful push, pull or clone.
+pub fn get_base_db_path(remote_name: &str) -> PathBuf {
+    PathBuf::from(".scuttle/remotes").join(format!("{}.db", remote_name))
+}
+
+/// Remember the hash of the remote DB both sides agreed on at the last sync.
+pub fn set_synced_db_hash(remote_name: &str, db_hash: &str) -> Result<()> {
+    let mut config = load_repo_config()?;
+    let binding = &mut config["remotes"][remote_name];
+    if !binding.is_object() {
+        *binding = serde_json::json!({});
+    }
+    binding["synced_db_hash"] = serde_json::json!(db_hash);
+    save_repo_config(&config)
+}
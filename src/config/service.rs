@@ -10,6 +10,30 @@ pub fn get_config_path() -> Result<std::path::PathBuf> {
     let config_file_path = app_config_dir.join("config.json");
     Ok(config_file_path)
 }
+/// The user-level ignore file, applied to every repo below its own `.scuttleignore` files.
+pub fn get_global_ignore_path() -> Result<std::path::PathBuf> {
+    let config_path = get_config_path()?;
+    Ok(config_path.with_file_name("ignore"))
+}
+
+/// Exclude patterns of a remote config, from its optional `exclude` list.
+pub fn get_remote_excludes(config: &serde_json::Value) -> Vec<String> {
+    config.get("exclude")
+        .and_then(|e| e.as_array())
+        .map(|patterns| patterns.iter().filter_map(|p| p.as_str()).map(|p| p.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// How many transfers a remote runs at once by default, from its optional `jobs` setting.
+pub fn get_remote_jobs(config: &serde_json::Value) -> Option<usize> {
+    config.get("jobs").and_then(|j| j.as_u64()).map(|j| j as usize)
+}
+
+/// Chunk size in bytes for resumable uploads, from a remote's optional `upload_chunk_mib` setting.
+pub fn get_upload_chunk_size(config: &serde_json::Value) -> Option<u64> {
+    config.get("upload_chunk_mib").and_then(|c| c.as_u64()).map(|mib| mib * 1024 * 1024)
+}
+
 pub fn get_configs() -> Result<Vec<serde_json::Value>> {
     let config_path = get_config_path()?;
     if !config_path.exists() {
@@ -26,20 +50,20 @@ pub fn get_config_detail(remote_name: Option<&str>) -> Result<Option<serde_json:
         Some(name) if !name.is_empty() => {
             // Search for config with matching remote name
             for config in &configs {
-                if let Some(config_name) = config.get("remote_name") {
-                    if config_name == name {
-                        return Ok(Some(config.clone()));
-                    }
+                if let Some(config_name) = config.get("remote_name")
+                    && config_name == name
+                {
+                    return Ok(Some(config.clone()));
                 }
             }
         }
         _ => {
             // Return the default config if it exists
             for config in &configs {
-                if let Some(is_default) = config.get("default") {
-                    if is_default.as_bool().unwrap_or(false) {
-                        return Ok(Some(config.clone()));
-                    }
+                if let Some(is_default) = config.get("default")
+                    && is_default.as_bool().unwrap_or(false)
+                {
+                    return Ok(Some(config.clone()));
                 }
             }
         }
@@ -0,0 +1,68 @@
+use similar::TextDiff;
+
+/// Files larger than this are summarised instead of diffed.
+pub const MAX_DIFF_BYTES: u64 = 8 * 1024 * 1024;
+
+/// One side of a file comparison.
+pub enum DiffContent {
+    /// The file does not exist on this side.
+    Missing,
+    Text(String),
+    /// The content is binary, so only its size and hash are shown.
+    Binary { size: u64, hash: String },
+    /// The content is over `MAX_DIFF_BYTES`, so only its size and hash are shown.
+    TooLarge { size: u64, hash: String },
+    /// The file exists, but only its hash is known (content not in the object store).
+    Unavailable(String),
+}
+
+impl DiffContent {
+    fn text(&self) -> Option<&str> {
+        match self {
+            DiffContent::Missing => Some(""),
+            DiffContent::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> String {
+        match self {
+            DiffContent::Missing => "(none)".to_string(),
+            DiffContent::Text(text) => format!("{} bytes", text.len()),
+            DiffContent::Binary { size, hash } => format!("{} bytes, {}", size, &hash[..hash.len().min(12)]),
+            DiffContent::TooLarge { size, hash } => format!("{} bytes, {}, too large to diff", size, &hash[..hash.len().min(12)]),
+            DiffContent::Unavailable(hash) => format!("content not available locally, {}", &hash[..hash.len().min(12)]),
+        }
+    }
+}
+
+/// Print a unified diff for text files, or a size/hash summary for binaries, large
+/// files and content that is not available locally.
+pub fn print_file_diff(path: &str, old: &DiffContent, new: &DiffContent) {
+    let old_label = match old {
+        DiffContent::Missing => "/dev/null".to_string(),
+        _ => format!("a/{}", path),
+    };
+    let new_label = match new {
+        DiffContent::Missing => "/dev/null".to_string(),
+        _ => format!("b/{}", path),
+    };
+    println!("diff a/{} b/{}", path, path);
+
+    match (old.text(), new.text()) {
+        (Some(old_text), Some(new_text)) => {
+            let diff = TextDiff::from_lines(old_text, new_text);
+            print!("{}", diff.unified_diff().context_radius(3).header(&old_label, &new_label));
+        }
+        _ => {
+            let binary = |c: &DiffContent| matches!(c, DiffContent::Binary { .. });
+            if binary(old) || binary(new) {
+                println!("Binary files {} and {} differ", old_label, new_label);
+            } else {
+                println!("Files {} and {} differ", old_label, new_label);
+            }
+            println!("  old: {}", old.summary());
+            println!("  new: {}", new.summary());
+        }
+    }
+}
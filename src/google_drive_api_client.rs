@@ -6,10 +6,29 @@ use yup_oauth2::{
     read_application_secret, InstalledFlowAuthenticator, InstalledFlowReturnMethod,
 };
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hyper::body::HttpBody;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use google_drive3::client::Retry;
+use crate::remote::{RemoteEntry, RemoteError, RemoteStorage};
+use crate::utils::hashing::md5_file;
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+const FILES_ENDPOINT: &str = "https://www.googleapis.com/drive/v3/files";
+const UPLOAD_ENDPOINT: &str = "https://www.googleapis.com/upload/drive/v3/files";
+/// Drive requires resumable chunks to be multiples of 256 KiB.
+const UPLOAD_CHUNK_ALIGN: u64 = 256 * 1024;
+pub const DEFAULT_UPLOAD_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// Where interrupted upload sessions are remembered so the next push can resume them.
+const UPLOAD_SESSIONS_PATH: &str = ".scuttle/upload_sessions.json";
+/// Drive keeps a session for a week; older ones are not worth trying.
+const UPLOAD_SESSION_MAX_AGE_SECS: u64 = 6 * 24 * 60 * 60;
 
 /// Creates and authenticates a new Google Drive client.
 /// This function handles the OAuth2 flow and token persistence.
-async fn create_drive_client(remote_server_name: &String) -> Result<DriveHub<HttpsConnector<HttpConnector>>> {
+async fn create_drive_client(remote_server_name: &str) -> Result<DriveHub<HttpsConnector<HttpConnector>>> {
     let config_dir = dirs::config_dir().context("Could not find config directory")?;
     let app_config_dir = config_dir.join("scuttle");
     if !app_config_dir.exists() {
@@ -45,7 +64,7 @@ async fn create_drive_client(remote_server_name: &String) -> Result<DriveHub<Htt
 }
 
 /// A test function to get a drive client and list the first 10 files.
-pub async fn get_drive_client(remote_server_name: &String) -> Result<DriveHub<HttpsConnector<HttpConnector>>> {
+pub async fn get_drive_client(remote_server_name: &str) -> Result<DriveHub<HttpsConnector<HttpConnector>>> {
     let drive_client = create_drive_client(remote_server_name).await?;
     println!("Drive client created and authenticated.");
     println!("Testing client by fetching file list...");
@@ -85,339 +104,740 @@ pub async fn get_drive_client(remote_server_name: &String) -> Result<DriveHub<Ht
     Ok(drive_client)
 }
 
-/// Uploads a single file to Google Drive.
-pub async fn upload_file(path: &std::path::Path, remote_server_name: &String) -> bool {
-    println!("Uploading file: {:?}", path);
-    let drive_client = match create_drive_client(remote_server_name).await {
-        Ok(client) => client,
-        Err(e) => {
-            eprintln!("Failed to create drive client: {}", e);
-            return false;
-        }
-    };
+fn to_remote_entry(file: google_drive3::api::File) -> RemoteEntry {
+    RemoteEntry {
+        is_folder: file.mime_type.as_deref() == Some(FOLDER_MIME_TYPE),
+        id: file.id.unwrap_or_default(),
+        name: file.name.unwrap_or_default(),
+        size: file.size.map(|s| s as u64),
+    }
+}
 
-    let file = match std::fs::File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Failed to open file {:?}: {}", path, e);
-            return false;
-        }
-    };
+fn is_not_found(err: &google_drive3::Error) -> bool {
+    match err {
+        google_drive3::Error::BadRequest(value) => value["error"]["code"].as_u64() == Some(404),
+        google_drive3::Error::Failure(response) => response.status() == hyper::StatusCode::NOT_FOUND,
+        _ => false,
+    }
+}
+
+/// Keeps the `Retry-After` hint of a failed call: the generated client drops the
+/// response headers when Drive describes the error in a JSON body.
+#[derive(Default)]
+struct FailureProbe {
+    retry_after: Option<Duration>,
+}
+
+impl google_drive3::Delegate for FailureProbe {
+    fn http_failure(&mut self, response: &hyper::Response<hyper::Body>, _err: Option<serde_json::Value>) -> Retry {
+        self.retry_after = retry_after(response.headers());
+        Retry::Abort
+    }
+}
 
-    // Define file metadata.
-    let metadata = google_drive3::api::File {
-        name: Some(path.file_name().unwrap().to_string_lossy().to_string()),
-        ..Default::default()
-    };
+/// The delay a `Retry-After` header asks for, given in seconds or as an HTTP date.
+fn retry_after(headers: &hyper::HeaderMap) -> Option<Duration> {
+    let value = headers.get(hyper::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
 
-    let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
+/// Classify a failed HTTP response so the retry policy knows whether to try
+/// again: rate limits and server errors are worth retrying, anything else is not.
+fn failure_error(action: &str, status: hyper::StatusCode, body: &[u8], retry_after: Option<Duration>) -> anyhow::Error {
+    let error: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    let reason = error["error"]["errors"][0]["reason"].as_str();
+    let message = format!("{}: {} {}", action, status, String::from_utf8_lossy(body));
+    classify_status(status.as_u16(), reason, retry_after, message)
+}
 
-    // Perform the upload request.
-    let request = drive_client
-        .files()
-        .create(metadata)
-        // Add the correct scope for uploading files.
-        .add_scope(Scope::Full)
-        .upload(file, mime_type);
+fn classify_status(status: u16, reason: Option<&str>, retry_after: Option<Duration>, message: String) -> anyhow::Error {
+    let rate_limited = status == 429
+        || (status == 403 && matches!(reason, Some("userRateLimitExceeded" | "rateLimitExceeded")));
+    if rate_limited {
+        RemoteError::RateLimited { message, retry_after }.into()
+    } else if status == 408 || status >= 500 {
+        RemoteError::Transient(message).into()
+    } else {
+        anyhow::anyhow!(message)
+    }
+}
+
+/// Read the body of a failed response and classify it with `failure_error`.
+async fn response_error(action: &str, response: hyper::Response<hyper::Body>) -> anyhow::Error {
+    let status = response.status();
+    let retry_after = retry_after(response.headers());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap_or_default();
+    failure_error(action, status, &body, retry_after)
+}
 
-    match request.await {
-        Ok((_response, file)) => {
-            println!("File uploaded successfully: {}", file.name.unwrap_or_default());
-            true
+/// Classify an error from a generated Drive call, like `failure_error` does for raw responses.
+fn drive_error(action: &str, err: google_drive3::Error, probe: &FailureProbe) -> anyhow::Error {
+    let message = format!("{}: {}", action, err);
+    match &err {
+        google_drive3::Error::HttpError(_) | google_drive3::Error::Io(_) => RemoteError::Transient(message).into(),
+        google_drive3::Error::BadRequest(value) => {
+            let status = value["error"]["code"].as_u64().unwrap_or(0) as u16;
+            classify_status(status, value["error"]["errors"][0]["reason"].as_str(), probe.retry_after, message)
         }
-        Err(e) => {
-            eprintln!("Failed to upload file: {}", e);
-            false
+        google_drive3::Error::Failure(response) => {
+            classify_status(response.status().as_u16(), None, retry_after(response.headers()), message)
         }
+        _ => anyhow::anyhow!(message),
     }
 }
 
-/// Downloads a file by name from Google Drive, searching across all drives.
-pub async fn download_file(file_name: &str, destination_folder: &std::path::Path, remote_server_name: &String) -> bool {
-    use std::io::Write;
-    use hyper::body::HttpBody;
+/// `RemoteStorage` backed by Google Drive.
+///
+/// Owns one authenticated `DriveHub`, so a command reads credentials and
+/// builds the authenticator once, and every request shares its connection pool
+/// and cached access token.
+pub struct GoogleDriveRemote {
+    hub: DriveHub<HttpsConnector<HttpConnector>>,
+    /// Drive allows several folders with the same name, so concurrent transfers
+    /// resolve folders one at a time; the second finds what the first created.
+    folder_lock: tokio::sync::Mutex<()>,
+    /// Files larger than one chunk are uploaded resumably in chunks of this size.
+    chunk_size: u64,
+    /// Serializes updates of the upload sessions file between concurrent uploads.
+    sessions_lock: std::sync::Mutex<()>,
+}
 
-    println!("Downloading file '{}' with shared drive support...", file_name);
-    let drive_client = match create_drive_client(remote_server_name).await {
-        Ok(client) => client,
-        Err(e) => {
-            eprintln!("Failed to create drive client: {}", e);
-            return false;
-        }
-    };
-
-    // Search for the file by name to get its ID.
-    let result = drive_client.files().list()
-        .q(&format!("name = '{}' and trashed = false", file_name))
-        .param("fields", "files(id, name)")
-        .supports_all_drives(true)
-        .include_items_from_all_drives(true)
-        .add_scope(Scope::Readonly) // Scope to read metadata
-        .doit()
-        .await;
+/// What a resumable upload creates or replaces.
+enum UploadTarget<'a> {
+    Create { name: &'a str, parent_id: Option<&'a str> },
+    Update { file_id: &'a str },
+}
 
-    let file_id = match result {
-        Ok((_resp, file_list)) => {
-            if let Some(files) = file_list.files {
-                if let Some(file) = files.first() {
-                     file.id.clone().unwrap_or_default()
-                } else {
-                    eprintln!("No file found with name: {}", file_name);
-                    return false;
-                }
-            } else {
-                eprintln!("No files found in the API response.");
-                return false;
-            }
+impl UploadTarget<'_> {
+    /// Identifies the upload in the sessions file, together with the local path.
+    fn key(&self) -> String {
+        match self {
+            UploadTarget::Create { name, parent_id } => format!("create {}/{}", parent_id.unwrap_or("root"), name),
+            UploadTarget::Update { file_id } => format!("update {}", file_id),
         }
-        Err(e) => {
-            eprintln!("Failed to search for file by name: {}", e);
-            return false;
+    }
+
+    fn not_found(&self) -> RemoteError {
+        match self {
+            UploadTarget::Create { parent_id, .. } => RemoteError::NotFound(parent_id.unwrap_or("root").to_string()),
+            UploadTarget::Update { file_id } => RemoteError::NotFound(file_id.to_string()),
         }
-    };
+    }
+}
 
-    if file_id.is_empty() {
-        eprintln!("Could not find a valid file ID for '{}'", file_name);
-        return false;
+/// Where a resumable upload session stands, as reported by Drive.
+enum UploadProgress {
+    /// Drive has the bytes before this offset.
+    Offset(u64),
+    /// The upload completed; holds the id of the file.
+    Done(String),
+    /// The session expired or was never valid; start a new one.
+    Expired,
+}
+
+impl GoogleDriveRemote {
+    /// Authenticate as `remote_server_name`; create one per command and reuse it.
+    /// `chunk_size` is rounded down to a multiple of 256 KiB.
+    pub async fn new(remote_server_name: &str, chunk_size: u64) -> Result<Self> {
+        Ok(GoogleDriveRemote {
+            hub: create_drive_client(remote_server_name).await?,
+            folder_lock: tokio::sync::Mutex::new(()),
+            chunk_size: (chunk_size / UPLOAD_CHUNK_ALIGN).max(1) * UPLOAD_CHUNK_ALIGN,
+            sessions_lock: std::sync::Mutex::new(()),
+        })
     }
-    println!("Found file ID: {}", file_id);
 
+    /// Find a file by name across all drives. Returns the file ID if found.
+    async fn find_file_by_name(&self, file_name: &str) -> Result<Option<String>> {
+        let q = format!("name = '{}' and mimeType != 'application/vnd.google-apps.folder' and trashed = false", file_name);
+        let mut probe = FailureProbe::default();
+        let result = self.hub.files().list()
+            .q(&q)
+            .param("fields", "files(id, name)")
+            .supports_all_drives(true)
+            .include_items_from_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut probe)
+            .doit()
+            .await;
 
-    let destination_path = destination_folder.join(file_name);
-    let mut file = match std::fs::File::create(&destination_path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Failed to create destination file {:?}: {}", destination_path, e);
-            return false;
-        }
-    };
-
-    // Request file content with shared drive support.
-    let request = drive_client.files().get(&file_id)
-        .param("alt", "media")
-        .supports_all_drives(true)
-        // **FIX**: Add the correct scope to read file content.
-        .add_scope(Scope::Readonly);
-
-    match request.doit().await {
-        Ok((mut response, _)) => {
-            let mut downloaded: u64 = 0;
-            while let Some(chunk) = response.body_mut().data().await {
-                match chunk {
-                    Ok(bytes) => {
-                        if let Err(e) = file.write_all(&bytes) {
-                            eprintln!("Failed to write chunk to file: {}", e);
-                            return false;
-                        }
-                        downloaded += bytes.len() as u64;
-                        println!("Downloaded {} bytes...", downloaded);
-                    }
-                    Err(e) => {
-                        eprintln!("Error while downloading chunk: {}", e);
-                        return false;
-                    }
-                }
+        match result {
+            Ok((_resp, list)) => Ok(list.files.and_then(|files| files.into_iter().next()).and_then(|f| f.id)),
+            Err(e) => Err(drive_error("Failed to search for file by name", e, &probe)),
+        }
+    }
+
+    /// List the direct children of a folder, following pagination.
+    async fn list_folder(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+        let q = format!("'{}' in parents and trashed = false", folder_id);
+        let mut entries = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut probe = FailureProbe::default();
+            let mut call = self.hub.files().list()
+                .q(&q)
+                .param("fields", "nextPageToken, files(id, name, mimeType, size)")
+                .supports_all_drives(true)
+                .include_items_from_all_drives(true)
+                .add_scope(Scope::Readonly);
+            if let Some(token) = &page_token {
+                call = call.page_token(token);
+            }
+            let (_resp, list) = call.delegate(&mut probe).doit().await
+                .map_err(|e| drive_error("Failed to list folder", e, &probe))?;
+            entries.extend(list.files.unwrap_or_default().into_iter().map(to_remote_entry));
+            page_token = list.next_page_token;
+            if page_token.is_none() {
+                break;
             }
-            println!("File downloaded successfully to {:?}", destination_path);
-            true
         }
-        Err(e) => {
-            eprintln!("Failed to download file content: {}", e);
-            false
+        Ok(entries)
+    }
+
+    /// Fetch metadata for a file or folder by id. Returns `None` if Drive reports 404.
+    async fn get_file_metadata(&self, file_id: &str) -> Result<Option<RemoteEntry>> {
+        let mut probe = FailureProbe::default();
+        let result = self.hub.files().get(file_id)
+            .param("fields", "id, name, mimeType, size")
+            .supports_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut probe)
+            .doit()
+            .await;
+
+        match result {
+            Ok((_resp, file)) => Ok(Some(to_remote_entry(file))),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(drive_error("Failed to get file metadata", e, &probe)),
         }
     }
-}
 
-/// Find a folder by name at the root or across drives. Returns the file ID if found.
-pub async fn find_folder_by_name(folder_name: &str, remote_server_name: &String) -> Result<Option<String>> {
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let q = format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false", folder_name);
-    let result = drive_client.files().list()
-        .q(&q)
-        .param("fields", "files(id, name)")
-        .supports_all_drives(true)
-        .include_items_from_all_drives(true)
-        .add_scope(Scope::Readonly)
-        .doit()
-        .await;
+    /// Find a folder by name at the root or across drives. Returns the file ID if found.
+    async fn find_folder_by_name(&self, folder_name: &str) -> Result<Option<String>> {
+        let q = format!("name = '{}' and mimeType = 'application/vnd.google-apps.folder' and trashed = false", folder_name);
+        let mut probe = FailureProbe::default();
+        let result = self.hub.files().list()
+            .q(&q)
+            .param("fields", "files(id, name)")
+            .supports_all_drives(true)
+            .include_items_from_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut probe)
+            .doit()
+            .await;
 
-    match result {
-        Ok((_resp, list)) => {
-            if let Some(files) = list.files {
-                if let Some(file) = files.first() {
-                    Ok(file.id.clone())
+        match result {
+            Ok((_resp, list)) => {
+                if let Some(files) = list.files {
+                    if let Some(file) = files.first() {
+                        Ok(file.id.clone())
+                    } else {
+                        Ok(None)
+                    }
                 } else {
                     Ok(None)
                 }
-            } else {
-                Ok(None)
             }
+            Err(e) => Err(drive_error("Failed to search for folder", e, &probe)),
         }
-        Err(e) => Err(anyhow::anyhow!("Failed to search for folder: {}", e)),
     }
-}
 
-/// Find a file by name under a given parent folder id. Returns file id if found.
-pub async fn find_file_in_folder(file_name: &str, parent_id: &str, remote_server_name: &String) -> Result<Option<String>> {
-    let drive_client = create_drive_client(remote_server_name).await?;
-    // Query for name and parent
-    let q = format!("name = '{}' and '{}' in parents and trashed = false", file_name, parent_id);
-    let result = drive_client.files().list()
-        .q(&q)
-        .param("fields", "files(id, name)")
-        .supports_all_drives(true)
-        .include_items_from_all_drives(true)
-        .add_scope(Scope::Readonly)
-        .doit()
-        .await;
+    /// Find a file by name under a given parent folder id. Returns file id if found.
+    async fn find_file_in_folder(&self, file_name: &str, parent_id: &str) -> Result<Option<String>> {
+        // Query for name and parent
+        let q = format!("name = '{}' and '{}' in parents and trashed = false", file_name, parent_id);
+        let mut probe = FailureProbe::default();
+        let result = self.hub.files().list()
+            .q(&q)
+            .param("fields", "files(id, name)")
+            .supports_all_drives(true)
+            .include_items_from_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut probe)
+            .doit()
+            .await;
 
-    match result {
-        Ok((_resp, list)) => {
-            if let Some(files) = list.files {
-                if let Some(file) = files.first() {
-                    Ok(file.id.clone())
+        match result {
+            Ok((_resp, list)) => {
+                if let Some(files) = list.files {
+                    if let Some(file) = files.first() {
+                        Ok(file.id.clone())
+                    } else {
+                        Ok(None)
+                    }
                 } else {
                     Ok(None)
                 }
-            } else {
-                Ok(None)
             }
+            Err(e) => Err(drive_error("Failed to search for file in folder", e, &probe)),
         }
-        Err(e) => Err(anyhow::anyhow!("Failed to search for file in folder: {}", e)),
     }
-}
 
-use std::path::Path;
-use hyper::body::HttpBody;
+    /// Upload a file to Drive as `name` under optional parent_id. Returns the uploaded file ID on success.
+    async fn upload_file_with_parent(&self, path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+        if fs::metadata(path)?.len() > self.chunk_size {
+            let id = self.upload_resumable(&UploadTarget::Create { name, parent_id }, path).await?;
+            println!("Uploaded '{}' to remote (id={})", name, id);
+            return Ok(id);
+        }
+        let file = std::fs::File::open(path).context("Failed to open file for upload")?;
+
+        let metadata = google_drive3::api::File {
+            name: Some(name.to_string()),
+            parents: parent_id.map(|parent| vec![parent.to_string()]),
+            ..Default::default()
+        };
+
+        let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
+        // Build create call and set supports_all_drives when parent provided
+        let mut create_call = self.hub.files().create(metadata).add_scope(Scope::Full);
+        if parent_id.is_some() {
+            create_call = create_call.supports_all_drives(true);
+        }
 
-/// Upload a file to Drive under optional parent_id. Returns the uploaded file ID on success.
-pub async fn upload_file_with_parent(path: &Path, parent_id: Option<&str>, remote_server_name: &String) -> Result<String> {
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let file = std::fs::File::open(path).context("Failed to open file for upload")?;
-    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file").to_string();
+        let mut probe = FailureProbe::default();
+        let request = create_call.delegate(&mut probe).upload(file, mime_type);
+        match request.await {
+            Ok((_resp, file)) => {
+                println!("Uploaded '{}' to remote (id={})", name, file.id.clone().unwrap_or_default());
+                Ok(file.id.unwrap_or_default())
+            }
+            Err(e) if is_not_found(&e) => Err(RemoteError::NotFound(parent_id.unwrap_or("root").to_string()).into()),
+            Err(e) => Err(drive_error("Failed to upload file", e, &probe)),
+        }
+    }
 
-    let mut metadata = google_drive3::api::File::default();
-    metadata.name = Some(name.clone());
-    if let Some(parent) = parent_id {
-        metadata.parents = Some(vec![parent.to_string()]);
+    /// Replace the content of an existing file, keeping its id, revisions and sharing settings.
+    async fn update_file_content(&self, file_id: &str, path: &Path) -> Result<()> {
+        if fs::metadata(path)?.len() > self.chunk_size {
+            self.upload_resumable(&UploadTarget::Update { file_id }, path).await?;
+            println!("Updated remote file (id={})", file_id);
+            return Ok(());
+        }
+        let file = std::fs::File::open(path).context("Failed to open file for upload")?;
+
+        let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
+        let mut probe = FailureProbe::default();
+        let request = self.hub.files()
+            .update(google_drive3::api::File::default(), file_id)
+            .supports_all_drives(true)
+            .add_scope(Scope::Full)
+            .delegate(&mut probe)
+            .upload(file, mime_type);
+        match request.await {
+            Ok(_) => {
+                println!("Updated remote file (id={})", file_id);
+                Ok(())
+            }
+            Err(e) if is_not_found(&e) => Err(RemoteError::NotFound(file_id.to_string()).into()),
+            Err(e) => Err(drive_error("Failed to update file", e, &probe)),
+        }
     }
 
-    let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
-    // Build create call and set supports_all_drives when parent provided
-    let mut create_call = drive_client.files().create(metadata).add_scope(Scope::Full);
-    if parent_id.is_some() {
-        create_call = create_call.supports_all_drives(true);
+    /// Delete a file by id. Returns true if deleted.
+    async fn delete_file_by_id(&self, file_id: &str) -> Result<bool> {
+        let mut probe = FailureProbe::default();
+        let res = self.hub.files().delete(file_id).supports_all_drives(true).add_scope(Scope::Full).delegate(&mut probe).doit().await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Err(RemoteError::NotFound(file_id.to_string()).into()),
+            Err(e) => Err(drive_error("Failed to delete file", e, &probe)),
+        }
     }
 
-    let request = create_call.upload(file, mime_type);
-    match request.await {
-        Ok((_resp, file)) => {
-            println!("Uploaded '{}' to remote (id={})", name, file.id.clone().unwrap_or_default());
-            Ok(file.id.unwrap_or_default())
+    /// Download a file by id into the specified destination path. If the destination
+    /// already holds the start of the file from an interrupted download, only the
+    /// rest is fetched. The result is checked against Drive's MD5 checksum, and
+    /// removed if it does not match.
+    async fn download_file_by_id(&self, file_id: &str, destination: &Path) -> Result<()> {
+        let mut probe = FailureProbe::default();
+        let result = self.hub.files().get(file_id)
+            .param("fields", "size, md5Checksum")
+            .supports_all_drives(true)
+            .add_scope(Scope::Readonly)
+            .delegate(&mut probe)
+            .doit()
+            .await;
+        let (size, md5) = match result {
+            Ok((_resp, file)) => (file.size.map(|s| s as u64), file.md5_checksum),
+            Err(e) if is_not_found(&e) => return Err(RemoteError::NotFound(file_id.to_string()).into()),
+            Err(e) => return Err(drive_error("Failed to get file metadata", e, &probe)),
+        };
+
+        // A partial file longer than the remote one, or of unknown length, cannot be resumed
+        let existing = fs::metadata(destination).map(|m| m.len()).unwrap_or(0);
+        let offset = match size {
+            Some(size) if existing <= size => existing,
+            _ => 0,
+        };
+        if offset == 0 || Some(offset) != size {
+            self.fetch_from(file_id, destination, offset).await?;
+        }
+
+        if let Some(expected) = md5
+            && md5_file(destination)? != expected
+        {
+            fs::remove_file(destination).ok();
+            return Err(anyhow::anyhow!("Downloaded content of {} does not match Drive's checksum", file_id));
         }
-        Err(e) => Err(anyhow::anyhow!("Failed to upload file: {}", e)),
+        Ok(())
     }
-}
 
-/// Delete a file by id. Returns true if deleted.
-pub async fn delete_file_by_id(file_id: &str, remote_server_name: &String) -> Result<bool> {
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let res = drive_client.files().delete(file_id).add_scope(Scope::Full).doit().await;
-    if let Err(e) = res {
-        return Err(anyhow::anyhow!("Failed to delete file: {}", e));
+    /// Write the content of `file_id` from byte `offset` on into `destination`,
+    /// appending to what is there. The generated client cannot send a `Range`
+    /// header, so this requests the media directly.
+    async fn fetch_from(&self, file_id: &str, destination: &Path, offset: u64) -> Result<()> {
+        use std::io::Write;
+        let mut request = hyper::Request::builder()
+            .method(hyper::Method::GET)
+            .uri(format!("{}/{}?alt=media&supportsAllDrives=true", FILES_ENDPOINT, file_id))
+            .header(hyper::header::AUTHORIZATION, format!("Bearer {}", self.access_token().await?));
+        if offset > 0 {
+            request = request.header(hyper::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = self.hub.client.request(request.body(hyper::Body::empty())?).await
+            .map_err(|e| RemoteError::Transient(format!("Failed to download file by id: {}", e)))?;
+        let status = response.status();
+        let mut file = if status == hyper::StatusCode::PARTIAL_CONTENT {
+            fs::OpenOptions::new().append(true).open(destination).context("Failed to open partial download")?
+        } else if status.is_success() {
+            // The whole file, e.g. when no range was asked for
+            fs::File::create(destination).context("Failed to create destination file")?
+        } else if status == hyper::StatusCode::NOT_FOUND {
+            return Err(RemoteError::NotFound(file_id.to_string()).into());
+        } else {
+            return Err(response_error("Failed to download file by id", response).await);
+        };
+        while let Some(chunk) = response.body_mut().data().await {
+            let bytes = chunk.map_err(|e| RemoteError::Transient(format!("Download interrupted: {}", e)))?;
+            file.write_all(&bytes).context("Failed to write to destination file")?;
+        }
+        Ok(())
     }
-    Ok(true)
-}
 
-/// Download a file by id into the specified destination path.
-pub async fn download_file_by_id(file_id: &str, destination: &Path, remote_server_name: &String) -> Result<()> {
-    use std::io::Write;
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let request = drive_client.files().get(file_id).param("alt", "media").supports_all_drives(true).add_scope(Scope::Readonly);
-    match request.doit().await {
-        Ok((mut response, _)) => {
-            let mut file = std::fs::File::create(destination).context("Failed to create destination file")?;
-            while let Some(chunk) = response.body_mut().data().await {
-                let bytes = chunk.context("Error reading response chunk")?;
-                file.write_all(&bytes).context("Failed to write to destination file")?;
+    /// Create a folder with given name under optional parent. Returns folder id.
+    async fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let metadata = google_drive3::api::File {
+            name: Some(name.to_string()),
+            mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+            parents: parent_id.map(|p| vec![p.to_string()]),
+            ..Default::default()
+        };
+
+        // Use the upload method with an empty reader for folder creation (works with generated client).
+        let empty_reader = std::io::Cursor::new(Vec::new());
+        let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
+        let mut create_call = self.hub.files().create(metadata).add_scope(Scope::Full);
+        if parent_id.is_some() {
+            create_call = create_call.supports_all_drives(true);
+        }
+        let mut probe = FailureProbe::default();
+        let res = create_call.delegate(&mut probe).upload(empty_reader, mime_type).await;
+        match res {
+            Ok((_resp, file)) => Ok(file.id.unwrap_or_default()),
+            Err(e) => Err(drive_error("Failed to create folder", e, &probe)),
+        }
+    }
+
+    /// Ensure a remote path (sequence of folders) exists under given root_id. 
+    /// `relative_path` uses POSIX-style separators and should not start with `.`. 
+    /// Returns the folder id corresponding to the deepest folder (or root_id if no folders needed).
+    async fn ensure_remote_path(&self, root_id: &str, relative_path: &str) -> Result<String> {
+        // Split path into components and create folders as needed under root_id
+        let _guard = self.folder_lock.lock().await;
+        let mut parent = root_id.to_string();
+
+        // Normalize separators and skip filename
+        let path = relative_path.replace('\\', "/");
+        let components: Vec<&str> = path.split('/').collect();
+        if components.is_empty() {
+            return Ok(parent);
+        }
+
+        // Iterate over components except the last if it's a file (we assume caller passes directory path)
+        for comp in components.iter() {
+            let name = comp.trim();
+            if name.is_empty() { continue; }
+
+            // Check if folder exists with this name under current parent
+            let q = format!("name = '{}' and '{}' in parents and mimeType = 'application/vnd.google-apps.folder' and trashed = false", name, parent);
+            let mut probe = FailureProbe::default();
+            let res = self.hub.files().list()
+                .q(&q)
+                .param("fields", "files(id, name)")
+                .supports_all_drives(true)
+                .add_scope(Scope::Readonly)
+                .delegate(&mut probe)
+                .doit()
+                .await;
+
+            match res {
+                Ok((_resp, list)) => {
+                    if let Some(file) = list.files.as_ref().and_then(|files| files.first()) {
+                        parent = file.id.clone().unwrap_or(parent.clone());
+                        continue;
+                    }
+                    // Not found -> create
+                    let id = self.create_folder(name, Some(&parent)).await?;
+                    parent = id;
+                }
+                Err(e) => {
+                    return Err(drive_error("Failed to ensure remote path", e, &probe));
+                }
             }
-            Ok(())
         }
-        Err(e) => Err(anyhow::anyhow!("Failed to download file by id: {}", e)),
+
+        Ok(parent)
     }
 }
 
-/// Create a folder with given name under optional parent. Returns folder id.
-pub async fn create_folder(name: &str, parent_id: Option<&str>, remote_server_name: &String) -> Result<String> {
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let mut metadata = google_drive3::api::File::default();
-    metadata.name = Some(name.to_string());
-    metadata.mime_type = Some("application/vnd.google-apps.folder".to_string());
-    if let Some(p) = parent_id {
-        metadata.parents = Some(vec![p.to_string()]);
+/// Resumable uploads, talking to Drive's upload endpoint directly: the generated
+/// client never asks its delegate for a stored session URI, so it cannot resume
+/// an upload started by an earlier run.
+impl GoogleDriveRemote {
+    async fn access_token(&self) -> Result<String> {
+        self.hub.auth.get_token(&[Scope::Full.as_ref()]).await
+            .map_err(|e| anyhow::anyhow!("Failed to get access token: {}", e))?
+            .ok_or_else(|| anyhow::anyhow!("No access token available"))
     }
 
-    // Use the upload method with an empty reader for folder creation (works with generated client).
-    let empty_reader = std::io::Cursor::new(Vec::new());
-    let mime_type = "application/octet-stream".parse::<mime::Mime>().unwrap();
-    let mut create_call = drive_client.files().create(metadata).add_scope(Scope::Full);
-    if parent_id.is_some() {
-        create_call = create_call.supports_all_drives(true);
-    }
-    let res = create_call.upload(empty_reader, mime_type).await;
-    match res {
-        Ok((_resp, file)) => Ok(file.id.unwrap_or_default()),
-        Err(e) => Err(anyhow::anyhow!("Failed to create folder: {}", e)),
+    /// Upload `path` in chunks, resuming the session a previous run left behind
+    /// for the same file and target if Drive still has it.
+    /// Returns the id of the uploaded file.
+    async fn upload_resumable(&self, target: &UploadTarget<'_>, path: &Path) -> Result<String> {
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+        let key = format!("{} from {}", target.key(), path.display());
+
+        if let Some(url) = self.load_upload_session(&key, size, modified)? {
+            match self.query_upload_progress(&url, size).await? {
+                UploadProgress::Done(id) => {
+                    self.save_upload_session(&key, None)?;
+                    return Ok(id);
+                }
+                UploadProgress::Offset(offset) => {
+                    println!("Resuming upload of {} at {} of {} bytes", path.display(), offset, size);
+                    match self.send_chunks(&url, path, offset, size).await? {
+                        UploadProgress::Done(id) => {
+                            self.save_upload_session(&key, None)?;
+                            return Ok(id);
+                        }
+                        _ => println!("Upload session for {} expired; starting over.", path.display()),
+                    }
+                }
+                UploadProgress::Expired => println!("Upload session for {} expired; starting over.", path.display()),
+            }
+        }
+
+        let url = self.start_upload_session(target, size).await?;
+        self.save_upload_session(&key, Some((&url, size, modified)))?;
+        match self.send_chunks(&url, path, 0, size).await? {
+            UploadProgress::Done(id) => {
+                self.save_upload_session(&key, None)?;
+                Ok(id)
+            }
+            _ => Err(anyhow::anyhow!("Upload session for {} expired before it finished", path.display())),
+        }
     }
-}
 
-/// Ensure a remote path (sequence of folders) exists under given root_id. 
-/// `relative_path` uses POSIX-style separators and should not start with `.`. 
-/// Returns the folder id corresponding to the deepest folder (or root_id if no folders needed).
-pub async fn ensure_remote_path(root_id: &str, relative_path: &str, remote_server_name: &String) -> Result<String> {
-    // Split path into components and create folders as needed under root_id
-    let drive_client = create_drive_client(remote_server_name).await?;
-    let mut parent = root_id.to_string();
+    /// Ask Drive for a session URI to upload `size` bytes to `target`.
+    async fn start_upload_session(&self, target: &UploadTarget<'_>, size: u64) -> Result<String> {
+        let (method, url, metadata) = match target {
+            UploadTarget::Create { name, parent_id } => (
+                hyper::Method::POST,
+                format!("{}?uploadType=resumable&supportsAllDrives=true", UPLOAD_ENDPOINT),
+                serde_json::json!({ "name": name, "parents": parent_id.map(|p| vec![p]) }),
+            ),
+            UploadTarget::Update { file_id } => (
+                hyper::Method::PATCH,
+                format!("{}/{}?uploadType=resumable&supportsAllDrives=true", UPLOAD_ENDPOINT, file_id),
+                serde_json::json!({}),
+            ),
+        };
+        let request = hyper::Request::builder()
+            .method(method)
+            .uri(url)
+            .header(hyper::header::AUTHORIZATION, format!("Bearer {}", self.access_token().await?))
+            .header(hyper::header::CONTENT_TYPE, "application/json; charset=UTF-8")
+            .header("X-Upload-Content-Type", "application/octet-stream")
+            .header("X-Upload-Content-Length", size.to_string())
+            .body(hyper::Body::from(metadata.to_string()))?;
+        let response = self.hub.client.request(request).await
+            .map_err(|e| RemoteError::Transient(format!("Failed to start resumable upload: {}", e)))?;
+        let status = response.status();
+        if status == hyper::StatusCode::NOT_FOUND {
+            return Err(target.not_found().into());
+        }
+        if !status.is_success() {
+            return Err(response_error("Failed to start resumable upload", response).await);
+        }
+        response.headers().get(hyper::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .map(|l| l.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Drive did not return an upload session URI"))
+    }
 
-    // Normalize separators and skip filename
-    let path = relative_path.replace("\\", "/");
-    let components: Vec<&str> = path.split('/').collect();
-    if components.is_empty() {
-        return Ok(parent);
+    /// How much of a session's upload Drive has received.
+    async fn query_upload_progress(&self, url: &str, size: u64) -> Result<UploadProgress> {
+        let request = hyper::Request::builder()
+            .method(hyper::Method::PUT)
+            .uri(url)
+            .header(hyper::header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(hyper::Body::empty())?;
+        let response = self.hub.client.request(request).await
+            .map_err(|e| RemoteError::Transient(format!("Failed to query upload session: {}", e)))?;
+        upload_progress(response).await
     }
 
-    // Iterate over components except the last if it's a file (we assume caller passes directory path)
-    for comp in components.iter() {
-        let name = comp.trim();
-        if name.is_empty() { continue; }
+    /// Send `path` from `offset` to the end, one chunk per request.
+    async fn send_chunks(&self, url: &str, path: &Path, mut offset: u64, size: u64) -> Result<UploadProgress> {
+        let mut file = fs::File::open(path).context("Failed to open file for upload")?;
+        loop {
+            let len = self.chunk_size.min(size - offset);
+            let mut chunk = Vec::with_capacity(len as usize);
+            file.seek(SeekFrom::Start(offset))?;
+            (&mut file).take(len).read_to_end(&mut chunk)?;
+            if chunk.len() as u64 != len {
+                return Err(anyhow::anyhow!("{} changed during upload", path.display()));
+            }
+            let range = if len == 0 {
+                format!("bytes */{}", size)
+            } else {
+                format!("bytes {}-{}/{}", offset, offset + len - 1, size)
+            };
+            let request = hyper::Request::builder()
+                .method(hyper::Method::PUT)
+                .uri(url)
+                .header(hyper::header::CONTENT_RANGE, range)
+                .body(hyper::Body::from(chunk))?;
+            let response = self.hub.client.request(request).await
+                .map_err(|e| RemoteError::Transient(format!("Upload of {} interrupted at {} of {} bytes: {}", path.display(), offset, size, e)))?;
+            match upload_progress(response).await? {
+                // Drive may keep less than was sent; continue from what it reports
+                UploadProgress::Offset(received) => offset = received,
+                other => return Ok(other),
+            }
+        }
+    }
 
-        // Check if folder exists with this name under current parent
-        let q = format!("name = '{}' and '{}' in parents and mimeType = 'application/vnd.google-apps.folder' and trashed = false", name, parent);
-        let res = drive_client.files().list()
-            .q(&q)
-            .param("fields", "files(id, name)")
-            .supports_all_drives(true)
-            .add_scope(Scope::Readonly)
-            .doit()
-            .await;
+    /// The stored session URI for `key`, if it was started for the same file content and is recent enough.
+    fn load_upload_session(&self, key: &str, size: u64, modified: u64) -> Result<Option<String>> {
+        let _guard = self.sessions_lock.lock().unwrap();
+        let sessions = load_upload_sessions()?;
+        let Some(session) = sessions.get(key) else {
+            return Ok(None);
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let fresh = session["started"].as_u64().is_some_and(|t| now.saturating_sub(t) < UPLOAD_SESSION_MAX_AGE_SECS);
+        let same_file = session["size"].as_u64() == Some(size) && session["modified"].as_u64() == Some(modified);
+        Ok((fresh && same_file).then(|| session["url"].as_str().map(|u| u.to_string())).flatten())
+    }
 
-        match res {
-            Ok((_resp, list)) => {
-                if let Some(files) = list.files {
-                    if let Some(file) = files.first() {
-                        parent = file.id.clone().unwrap_or(parent.clone());
-                        continue;
-                    }
-                }
-                // Not found -> create
-                let id = create_folder(name, Some(&parent), remote_server_name).await?;
-                parent = id;
+    /// Remember the session URI of an upload in progress, or forget it with `None`.
+    fn save_upload_session(&self, key: &str, session: Option<(&str, u64, u64)>) -> Result<()> {
+        let _guard = self.sessions_lock.lock().unwrap();
+        // Outside a repo there is nowhere to keep sessions; uploads then just start over
+        let path = Path::new(UPLOAD_SESSIONS_PATH);
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Ok(());
+        }
+        let mut sessions = load_upload_sessions()?;
+        match session {
+            Some((url, size, modified)) => {
+                let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                sessions.insert(key.to_string(), serde_json::json!({ "url": url, "size": size, "modified": modified, "started": started }));
             }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Failed to ensure remote path: {}", e));
+            None => {
+                sessions.remove(key);
             }
         }
+        let data = serde_json::to_string_pretty(&sessions).context("Failed to serialize upload sessions")?;
+        fs::write(path, data).context("Failed to write upload sessions")
+    }
+}
+
+fn load_upload_sessions() -> Result<serde_json::Map<String, serde_json::Value>> {
+    let path = Path::new(UPLOAD_SESSIONS_PATH);
+    if !path.exists() {
+        return Ok(serde_json::Map::new());
     }
+    let data = fs::read_to_string(path).context("Failed to read upload sessions")?;
+    Ok(serde_json::from_str(&data).unwrap_or_default())
+}
 
-    Ok(parent)
+/// Interpret Drive's reply to a chunk or status request of a resumable upload.
+async fn upload_progress(response: hyper::Response<hyper::Body>) -> Result<UploadProgress> {
+    let status = response.status();
+    // 308 Resume Incomplete; `Range: bytes=0-N` says what Drive has
+    if status == hyper::StatusCode::PERMANENT_REDIRECT {
+        let received = response.headers().get(hyper::header::RANGE)
+            .and_then(|r| r.to_str().ok())
+            .and_then(|r| r.rsplit('-').next())
+            .and_then(|last| last.parse::<u64>().ok())
+            .map_or(0, |last| last + 1);
+        return Ok(UploadProgress::Offset(received));
+    }
+    let retry_after = retry_after(response.headers());
+    let body = hyper::body::to_bytes(response.into_body()).await
+        .map_err(|e| RemoteError::Transient(format!("Failed to read upload response: {}", e)))?;
+    if status.is_success() {
+        let file: google_drive3::api::File = serde_json::from_slice(&body).context("Failed to parse uploaded file metadata")?;
+        return Ok(UploadProgress::Done(file.id.unwrap_or_default()));
+    }
+    if status == hyper::StatusCode::NOT_FOUND || status == hyper::StatusCode::GONE {
+        return Ok(UploadProgress::Expired);
+    }
+    Err(failure_error("Resumable upload failed", status, &body, retry_after))
+}
+
+#[async_trait]
+impl RemoteStorage for GoogleDriveRemote {
+    async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+        self.list_folder(folder_id).await
+    }
+
+    async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>> {
+        self.get_file_metadata(id).await
+    }
+
+    async fn find_folder(&self, name: &str) -> Result<Option<String>> {
+        self.find_folder_by_name(name).await
+    }
+
+    async fn find_file(&self, name: &str) -> Result<Option<String>> {
+        self.find_file_by_name(name).await
+    }
+
+    async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>> {
+        self.find_file_in_folder(name, parent_id).await
+    }
+
+    async fn upload(&self, local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+        self.upload_file_with_parent(local_path, name, parent_id).await
+    }
+
+    async fn update(&self, id: &str, local_path: &Path) -> Result<()> {
+        self.update_file_content(id, local_path).await
+    }
+
+    async fn download(&self, id: &str, destination: &Path) -> Result<()> {
+        self.download_file_by_id(id, destination).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.delete_file_by_id(id).await.map(|_| ())
+    }
+
+    async fn mkdir(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        self.create_folder(name, parent_id).await
+    }
+
+    async fn ensure_path(&self, root_id: &str, rel_dir: &str) -> Result<String> {
+        self.ensure_remote_path(root_id, rel_dir).await
+    }
 }
@@ -1,21 +1,32 @@
 mod google_drive_api_client;
 mod config;
 mod utils;
+pub mod remote;
 
 use anyhow::{Context, Result};
 use std::fs;
-use crate::google_drive_api_client::{get_drive_client, upload_file, download_file, find_folder_by_name, find_file_in_folder, download_file_by_id, upload_file_with_parent, create_folder, delete_file_by_id, ensure_remote_path};
+use crate::google_drive_api_client::{get_drive_client, GoogleDriveRemote, DEFAULT_UPLOAD_CHUNK_SIZE};
+use crate::remote::{is_not_found, RemoteStorage};
+use crate::remote::local::LocalRemote;
+use crate::remote::retry::{RetryPolicy, RetryingRemote};
 use std::io::{self, Write};
-use google_drive3::DriveHub;
 use std::fs::File;
 mod sqlite_db;
-use std::collections::HashMap;
+mod diff;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use crate::sqlite_db::{ScuttleDb, TrackedFile};
-
-use crate::config::service::{get_config_detail, get_config_path};
-use crate::utils::hashing::hash_file;
-use crate::utils::filesystem::{load_scuttleignore, visit_dirs, add_file_to_db};
+use crate::sqlite_db::{ScuttleDb, SyncChanges, TrackedFile, TreeEntry};
+
+use crate::config::service::{get_config_detail, get_config_path, get_remote_excludes, get_remote_jobs, get_upload_chunk_size};
+use futures_util::stream::{self, StreamExt};
+use crate::config::repo::{find_repo_root, get_default_remote, get_remote_binding, set_remote_binding, get_base_db_path, set_synced_db_hash};
+use crate::utils::hashing::{hash_bytes, hash_file, hash_files, HashAlgorithm};
+use rayon::prelude::*;
+use crate::utils::ignore_rules::IgnoreRules;
+use crate::utils::filesystem::{load_scuttleignore, visit_dirs, add_file_to_db, cached_hash, file_stat, is_ignored, stage_stored_file};
+use crate::utils::objects::{object_path, restore_object, snapshot_path, store_object};
+use crate::utils::push_journal::{PlannedOp, PushJournal, PushOp, JOURNAL_DB_PATH};
+use crate::diff::{print_file_diff, DiffContent, MAX_DIFF_BYTES};
 
 #[derive(Debug)]
 pub enum Service {
@@ -23,6 +34,7 @@ pub enum Service {
     Dropbox,
     OneDrive,
     SMB,
+    Local,
 }
 
 impl Service {
@@ -32,6 +44,7 @@ impl Service {
             2 => Some(Service::Dropbox),
             3 => Some(Service::OneDrive),
             4 => Some(Service::SMB),
+            5 => Some(Service::Local),
             _ => None,
         }
     }
@@ -42,39 +55,146 @@ impl Service {
             Service::Dropbox => "dropbox",
             Service::OneDrive => "onedrive",
             Service::SMB => "smb",
+            Service::Local => "local",
         }
     }
 }
-pub async fn get_server_client(config: &serde_json::Value) -> Result<DriveHub<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>> {
-    if let Some(service) = config.get("service").and_then(|s| s.as_str()) {
-        match service {
-            "google_drive" => {
-                if let Some(remote_name) = config.get("remote_name").and_then(|n| n.as_str()) {
-                    let drive_client = get_drive_client(&remote_name.to_string()).await?;
-                    return Ok(drive_client);
-                } else {
-                    return Err(anyhow::anyhow!("remote_name not found in config"));
-                }
-            }
-            "dropbox" => {
-                // Initialize Dropbox client here
-                unimplemented!("Dropbox client not implemented yet");
-            }
-            "onedrive" => {
-                // Initialize OneDrive client here
-                unimplemented!("OneDrive client not implemented yet");
-            }
-            "smb" => {
-                // Initialize SMB client here
-                unimplemented!("SMB client not implemented yet");
-            }
-            _ => {
-                return Err(anyhow::anyhow!("Unsupported service: {}", service));
-            }
+
+/// How `pull` resolves paths that changed both locally and on the remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+    Interactive,
+}
+
+impl ConflictStrategy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keep-local" => Some(ConflictStrategy::KeepLocal),
+            "keep-remote" => Some(ConflictStrategy::KeepRemote),
+            "keep-both" => Some(ConflictStrategy::KeepBoth),
+            "interactive" => Some(ConflictStrategy::Interactive),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictStrategy::KeepLocal => "keep-local",
+            ConflictStrategy::KeepRemote => "keep-remote",
+            ConflictStrategy::KeepBoth => "keep-both",
+            ConflictStrategy::Interactive => "interactive",
+        }
+    }
+}
+
+/// Build the storage backend described by a remote config entry, retrying
+/// rate-limited and transient failures.
+pub async fn get_server_client(config: &serde_json::Value) -> Result<Box<dyn RemoteStorage>> {
+    let backend = get_backend(config).await?;
+    Ok(Box::new(RetryingRemote::new(backend, RetryPolicy::default())))
+}
+
+async fn get_backend(config: &serde_json::Value) -> Result<Box<dyn RemoteStorage>> {
+    let service = config.get("service")
+        .and_then(|s| s.as_str())
+        .ok_or_else(|| anyhow::anyhow!("service not found in config"))?;
+    let remote_name = config.get("remote_name")
+        .and_then(|n| n.as_str())
+        .ok_or_else(|| anyhow::anyhow!("remote_name not found in config"))?;
+
+    match service {
+        "google_drive" => {
+            let chunk_size = get_upload_chunk_size(config).unwrap_or(DEFAULT_UPLOAD_CHUNK_SIZE);
+            Ok(Box::new(GoogleDriveRemote::new(remote_name, chunk_size).await?))
+        }
+        "local" => {
+            let path = config.get("path")
+                .and_then(|p| p.as_str())
+                .ok_or_else(|| anyhow::anyhow!("path not found in config for local remote"))?;
+            Ok(Box::new(LocalRemote::new(Path::new(path))?))
+        }
+        "dropbox" | "onedrive" | "smb" => {
+            Err(anyhow::anyhow!("The {} service is not supported yet", service))
         }
+        _ => Err(anyhow::anyhow!("Unsupported service: {}", service)),
+    }
+}
+
+/// Change into the root of the repo containing the current directory, so the
+/// `.scuttle/...` paths used everywhere resolve there. Returns the directory
+/// the command was started from, relative to the root, for resolving path arguments.
+pub fn enter_repo_root() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to read current directory")?;
+    let root = find_repo_root(&cwd)
+        .ok_or_else(|| anyhow::anyhow!("Not a scuttle repository (or any parent up to /): {}. Run `scuttle init` first.", cwd.display()))?;
+    std::env::set_current_dir(&root).with_context(|| format!("Failed to enter repo root {}", root.display()))?;
+    Ok(cwd.strip_prefix(&root)?.to_path_buf())
+}
+
+/// Resolve a path argument given relative to `prefix` (see `enter_repo_root`), or
+/// absolute, to a path relative to the repo root. `.` stands for the root itself.
+pub fn repo_relative_path(prefix: &Path, path: &Path) -> Result<PathBuf> {
+    let joined = if path.is_absolute() {
+        // The root came from the current directory, so it has no symlinks; the argument may
+        // go through some, so it is canonicalized unless it already lies under the root
+        let root = std::env::current_dir()?;
+        let absolute = if path.starts_with(&root) {
+            path.to_path_buf()
+        } else {
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        };
+        absolute.strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .map_err(|_| anyhow::anyhow!("{} is outside the repository", path.display()))?
     } else {
-        return Err(anyhow::anyhow!("service not found in config"));
+        prefix.join(path)
+    };
+    let mut resolved = PathBuf::new();
+    for comp in joined.components() {
+        match comp {
+            std::path::Component::Normal(name) => resolved.push(name),
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir if resolved.pop() => {}
+            _ => return Err(anyhow::anyhow!("{} is outside the repository", path.display())),
+        }
+    }
+    if resolved.as_os_str().is_empty() {
+        resolved.push(".");
     }
+    Ok(resolved)
+}
+
+pub fn repo_relative_paths(prefix: &Path, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    paths.iter().map(|path| repo_relative_path(prefix, path)).collect()
+}
+
+/// Look up the remote config by name and fail if there is none. Without a name,
+/// the remote this repo is bound to wins over the global default.
+fn load_remote_config(remote_name: Option<&str>) -> Result<serde_json::Value> {
+    let bound_remote = match remote_name {
+        Some(_) => None,
+        None => get_default_remote()?,
+    };
+    get_config_detail(remote_name.or(bound_remote.as_deref()))?
+        .ok_or_else(|| anyhow::anyhow!("No configuration found. Please run setup first."))
+}
+
+/// Transfers run at once when neither `--jobs` nor the remote's `jobs` setting says otherwise.
+const DEFAULT_TRANSFER_JOBS: usize = 4;
+/// Downloads that have not finished or not been verified yet.
+const PARTIAL_DOWNLOADS_DIR: &str = ".scuttle/partial";
+
+/// How many uploads or downloads to run concurrently: `--jobs`, else the remote's `jobs` setting.
+fn transfer_jobs(jobs: Option<usize>, config: &serde_json::Value) -> usize {
+    jobs.or_else(|| get_remote_jobs(config)).unwrap_or(DEFAULT_TRANSFER_JOBS).max(1)
+}
+
+/// The rules deciding what gets pushed to a remote: the ignore files plus the remote's `exclude` list.
+fn load_push_rules(config: &serde_json::Value, remote_server: &str) -> Result<IgnoreRules> {
+    load_scuttleignore()?.with_excludes(&format!("remote {}", remote_server), &get_remote_excludes(config))
 }
 
 pub async fn process_upload(file_path: &Path, remote_name: Option<&str>) -> Result<()> {
@@ -83,50 +203,39 @@ pub async fn process_upload(file_path: &Path, remote_name: Option<&str>) -> Resu
         return Err(anyhow::anyhow!("File not found: {}", file_path.display()));
     }
 
-    // Read the file's contents into a vector of bytes.
-    // The `?` operator is a shortcut for error handling. It returns the error if `fs::read` fails.
-    let file_contents = fs::read(file_path).context("Failed to read file")?;
-    
-    // Use a placeholder message for now.
-    println!("File name: {}", file_path.file_name().unwrap().to_str().unwrap());
-    println!("File size: {} bytes", file_contents.len());
-    let confug_data = get_config_detail(remote_name)?;
-    if confug_data.is_none() {
-        return Err(anyhow::anyhow!("No configuration found. Please run setup first."));
-    }
-    let config = confug_data.unwrap();
-    let remote_server = config.get("remote_name")
-        .and_then(|n| n.as_str())
-        .map(|s| s.to_string())
-        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
-    let uploaded = upload_file(file_path, &remote_server).await;
-    if !uploaded {
-        return Err(anyhow::anyhow!("File upload failed"));
-    }
-    println!("Uploaded!");
+    // Read the file's metadata; the `?` operator returns the error if this fails.
+    let metadata = fs::metadata(file_path).context("Failed to read file")?;
+    let file_name = file_path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid file name: {}", file_path.display()))?;
+
+    println!("File name: {}", file_name);
+    println!("File size: {} bytes", metadata.len());
+    let config = load_remote_config(remote_name)?;
+    let remote = get_server_client(&config).await?;
+    let id = remote.upload(file_path, file_name, None).await
+        .context("File upload failed")?;
+    println!("Uploaded! (id={})", id);
 
     Ok(())
 }
 
-pub async fn process_download(remote_path: &String, remote_name: Option<&str>) -> Result<()> {
-    let confug_data = get_config_detail(remote_name)?;
-    if confug_data.is_none() {
-        return Err(anyhow::anyhow!("No configuration found. Please run setup first."));
-    }
-    let config = confug_data.unwrap();
-    let remote_server = config.get("remote_name")
-        .and_then(|n| n.as_str())
-        .map(|s| s.to_string())
-        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
-    let downloaded = download_file(remote_path, &std::path::Path::new(".").to_path_buf(), &remote_server).await;
-    if !downloaded {
-        return Err(anyhow::anyhow!("File download failed"));
-    }
-    println!("Downloaded!");
+pub async fn process_download(remote_path: &str, remote_name: Option<&str>) -> Result<()> {
+    let config = load_remote_config(remote_name)?;
+    let remote = get_server_client(&config).await?;
+    let file_id = remote.find_file(remote_path).await?
+        .ok_or_else(|| anyhow::anyhow!("No file found with name: {}", remote_path))?;
+    println!("Found file ID: {}", file_id);
+
+    let file_name = Path::new(remote_path).file_name().unwrap_or(remote_path.as_ref());
+    let destination = Path::new(".").join(file_name);
+    download_to_path(remote.as_ref(), &file_id, &destination, None).await
+        .context("File download failed")?;
+    println!("Downloaded to {}", destination.display());
     Ok(())
 }
 
-pub async fn process_init() -> anyhow::Result<()> {
+pub async fn process_init(hash_algorithm: Option<&str>) -> anyhow::Result<()> {
     // Create .scuttle directory
     let scuttle_dir = PathBuf::from(".scuttle");
     if !scuttle_dir.exists() {
@@ -149,9 +258,24 @@ pub async fn process_init() -> anyhow::Result<()> {
 
     // Initialize SQLite database inside .scuttle
     let db_path = scuttle_dir.join("scuttle.db");
-    let _db = ScuttleDb::new(&db_path)?;
+    let db = ScuttleDb::new(&db_path)?;
     println!("Initialized SQLite database at {}", db_path.display());
 
+    // Recorded hashes are only comparable under one algorithm, so it can
+    // only be chosen before anything has been tracked or committed
+    if let Some(name) = hash_algorithm {
+        let algorithm = HashAlgorithm::from_name(name)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported hash algorithm: {}", name))?;
+        let current = db.hash_algorithm()?;
+        if algorithm != current {
+            if !db.get_tracked_files()?.is_empty() || db.head()?.is_some() {
+                return Err(anyhow::anyhow!("Repo already tracks files hashed with {}; cannot switch to {}", current.as_str(), algorithm.as_str()));
+            }
+            db.set_hash_algorithm(algorithm)?;
+        }
+    }
+    println!("Hashing files with {}", db.hash_algorithm()?.as_str());
+
     Ok(())
 }
 
@@ -200,7 +324,7 @@ pub async fn process_setup() -> Result<()> {
 
     if update_input == "y" {
         // Ask user for service by number
-        println!("Select service by number:\n1. google_drive\n2. dropbox\n3. onedrive\n4. smb");
+        println!("Select service by number:\n1. google_drive\n2. dropbox\n3. onedrive\n4. smb\n5. local");
         io::stdout().flush().unwrap();
         let mut service_input = String::new();
         io::stdin().read_line(&mut service_input).context("Failed to read service input")?;
@@ -213,6 +337,19 @@ pub async fn process_setup() -> Result<()> {
         let mut remote_name = String::new();
         io::stdin().read_line(&mut remote_name).context("Failed to read remote name input")?;
         let remote_name = remote_name.trim();
+
+        // Local remotes need the directory that repos are mirrored into
+        let local_path = if let Service::Local = service {
+            println!("Enter the directory to mirror repos into (e.g. a NAS mount or USB drive): ");
+            io::stdout().flush().unwrap();
+            let mut path_input = String::new();
+            io::stdin().read_line(&mut path_input).context("Failed to read path input")?;
+            let path = PathBuf::from(path_input.trim());
+            fs::create_dir_all(&path).context("Failed to create local remote directory")?;
+            Some(fs::canonicalize(&path)?.to_string_lossy().to_string())
+        } else {
+            None
+        };
         // Ask user if they want to make this the default
         println!("Do you want to make this the default configuration? (y/n): ");
         io::stdout().flush().unwrap();
@@ -230,18 +367,23 @@ pub async fn process_setup() -> Result<()> {
         }
 
         // Add new config
-        let new_config = serde_json::json!({
+        let mut new_config = serde_json::json!({
             "service": service.as_str(),
             "remote_name": remote_name,
             "default": make_default
         });
+        if let Some(path) = local_path {
+            new_config["path"] = serde_json::json!(path);
+        }
         configs.push(new_config);
 
         // Write updated configs
         let updated_json = serde_json::to_string_pretty(&configs).context("Failed to serialize updated config")?;
         fs::write(&config_file_path, updated_json).context("Failed to write updated config file")?;
         println!("Config file updated with user settings.");
-        let _drive_client = get_drive_client(&remote_name.to_string()).await?;
+        if let Service::GoogleDrive = service {
+            let _drive_client = get_drive_client(remote_name).await?;
+        }
         println!("Initialized!");
     } else {
         println!("Using existing config.");
@@ -252,61 +394,159 @@ pub async fn process_setup() -> Result<()> {
 }
 
 
-pub async fn process_status() -> Result<()> {
+/// How `scuttle status` prints its result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFormat {
+    Long,
+    Short,
+    Porcelain,
+}
+
+/// Hashes of tracked files, from the stat cache when their stat is unchanged (and not
+/// `refresh`). The rest are hashed in parallel; those that still match the DB get
+/// their stat recorded.
+fn tracked_file_hashes(db: &ScuttleDb, files: &[(&TrackedFile, PathBuf)], refresh: bool) -> Result<Vec<String>> {
+    let mut hashes = vec![String::new(); files.len()];
+    let mut misses = Vec::new();
+    for (i, (tracked, local_path)) in files.iter().enumerate() {
+        let stat = file_stat(local_path)?;
+        match cached_hash(tracked, &stat).filter(|_| !refresh) {
+            Some(hash) => hashes[i] = hash,
+            None => misses.push((i, stat)),
+        }
+    }
+
+    let miss_paths: Vec<PathBuf> = misses.iter().map(|(i, _)| files[*i].1.clone()).collect();
+    for ((i, stat), hash) in misses.into_iter().zip(hash_files(&miss_paths, db.hash_algorithm()?)) {
+        let hash = hash?;
+        let tracked = files[i].0;
+        if tracked.hash.as_deref() == Some(hash.as_str()) {
+            db.set_stat(&tracked.path, stat.size, stat.last_modified, stat.inode, stat.ctime)?;
+        }
+        hashes[i] = hash;
+    }
+    Ok(hashes)
+}
+
+pub async fn process_status(format: StatusFormat, show_unchanged: bool, refresh: bool) -> Result<()> {
     // Load tracked files from database
     let db = ScuttleDb::new(&PathBuf::from(".scuttle/scuttle.db"))?;
     let tracked_files = db.get_tracked_files()?;
     let mut tracked_map: HashMap<String, &TrackedFile> = HashMap::new();
-    for file in &tracked_files {
+    for file in tracked_files.iter().filter(|f| f.status.as_deref() != Some("deleted")) {
         tracked_map.insert(file.path.clone(), file);
     }
 
     // Scan local files recursively excluding .scuttle and respecting .scuttleignore
-    let ignore_patterns = load_scuttleignore()?;
+    let ignore_rules = load_scuttleignore()?;
     let mut local_files = Vec::new();
-    visit_dirs(Path::new("."), &ignore_patterns, &mut local_files)?;
+    visit_dirs(Path::new("."), &ignore_rules, &mut local_files)?;
 
     // Map local files by relative path
-    let mut local_map: HashMap<String, PathBuf> = HashMap::new();
+    let mut local_map: BTreeMap<String, PathBuf> = BTreeMap::new();
     for path in &local_files {
         if let Ok(rel_path) = path.strip_prefix(".") {
             local_map.insert(rel_path.to_string_lossy().to_string(), path.clone());
         }
     }
 
-    // Compare and print status
-    println!("Status:");
-
-    // Check for new or modified files
+    // Staged: what the next commit records. Unstaged: working copy vs staged state.
+    let staged: BTreeMap<String, String> = db.staged_changes()?.into_iter().collect();
+    let mut unstaged: BTreeMap<String, &str> = BTreeMap::new();
+    let mut untracked = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut present = Vec::new();
     for (rel_path, local_path) in &local_map {
-        if let Some(tracked) = tracked_map.get(rel_path) {
-            // Compare hash
-            let local_hash = hash_file(local_path)?;
-            if Some(local_hash) != tracked.hash {
-                println!("Modified: {}", rel_path);
-            } else {
-                println!("Unchanged: {}", rel_path);
-            }
-        } else {
-            println!("New: {}", rel_path);
+        match tracked_map.get(rel_path) {
+            Some(tracked) => present.push((*tracked, local_path.clone())),
+            None => untracked.push(rel_path.clone()),
+        }
+    }
+    let hashes = tracked_file_hashes(&db, &present, refresh)?;
+    for ((tracked, _), hash) in present.iter().zip(hashes) {
+        if tracked.hash.as_deref() != Some(hash.as_str()) {
+            unstaged.insert(tracked.path.clone(), "modified");
+        } else if !staged.contains_key(&tracked.path) {
+            unchanged.push(tracked.path.clone());
         }
     }
-
-    // Check for deleted files
     for rel_path in tracked_map.keys() {
         if !local_map.contains_key(rel_path) {
-            println!("Deleted: {}", rel_path);
+            unstaged.insert(rel_path.clone(), "deleted");
+        }
+    }
+
+    if format != StatusFormat::Long {
+        let code = |change: &str| match change {
+            "added" => 'A',
+            "modified" => 'M',
+            "deleted" => 'D',
+            _ => ' ',
+        };
+        let paths: BTreeSet<&String> = staged.keys().chain(unstaged.keys()).collect();
+        for path in paths {
+            let x = staged.get(path).map(|c| code(c)).unwrap_or(' ');
+            let y = unstaged.get(path).map(|c| code(c)).unwrap_or(' ');
+            println!("{}{} {}", x, y, path);
+        }
+        for path in &untracked {
+            println!("?? {}", path);
+        }
+        if show_unchanged {
+            for path in &unchanged {
+                println!("   {}", path);
+            }
         }
+        return Ok(());
+    }
+
+    let label = |change: &str| match change {
+        "added" => "new file:",
+        "modified" => "modified:",
+        "deleted" => "deleted:",
+        _ => "changed:",
+    };
+    if !staged.is_empty() {
+        println!("Changes to be committed:");
+        for (path, change) in &staged {
+            println!("  {:<12}{}", label(change), path);
+        }
+        println!();
+    }
+    if !unstaged.is_empty() {
+        println!("Changes not staged for commit:");
+        println!("  (use \"scuttle add <file>...\" to stage them)");
+        for (path, change) in &unstaged {
+            println!("  {:<12}{}", label(change), path);
+        }
+        println!();
+    }
+    if !untracked.is_empty() {
+        println!("Untracked files:");
+        for path in &untracked {
+            println!("  {}", path);
+        }
+        println!();
+    }
+    if show_unchanged && !unchanged.is_empty() {
+        println!("Unchanged files:");
+        for path in &unchanged {
+            println!("  {}", path);
+        }
+        println!();
+    }
+    if staged.is_empty() && unstaged.is_empty() && untracked.is_empty() {
+        println!("Nothing to commit, working tree clean");
     }
 
     Ok(())
 }
 
-pub async fn process_add(paths: &[PathBuf]) -> anyhow::Result<()> {
+pub async fn process_add(paths: &[PathBuf], refresh: bool) -> anyhow::Result<()> {
     let db = ScuttleDb::new(Path::new(".scuttle/scuttle.db"))?;
 
-    // Load ignore patterns
-    let ignore_patterns = load_scuttleignore()?;
+    // Load ignore rules
+    let ignore_rules = load_scuttleignore()?;
     let paths_to_process = paths.to_vec();
 
     // Get currently tracked files and map by path
@@ -316,62 +556,54 @@ pub async fn process_add(paths: &[PathBuf]) -> anyhow::Result<()> {
         tracked_map.insert(file.path.clone(), file);
     }
 
+    let strip_dot = |path: &Path| path.strip_prefix(".").map(Path::to_path_buf).unwrap_or_else(|_| path.to_path_buf());
+    let mut candidates = BTreeSet::new();
     for path in &paths_to_process {
         // If path does not exist, mark it deleted instead of returning an error
         if !path.exists() {
-            let path_stripped = if let Ok(stripped) = path.strip_prefix(".") {
-                stripped.to_path_buf()
-            } else {
-                path.to_path_buf()
-            };
-            add_file_to_db(&db, &ignore_patterns, &path_stripped)?; // add_file_to_db will mark deleted when missing
+            add_file_to_db(&db, Some(&ignore_rules), &strip_dot(path))?; // add_file_to_db will mark deleted when missing
             continue;
         }
 
+        if is_ignored(path, &ignore_rules)? {
+            continue;
+        }
         if path.is_dir() {
             // Recursively add files in directory
             let mut files = Vec::new();
-            visit_dirs(path, &ignore_patterns, &mut files)?;
-            for file_path in files {
-                let file_path_stripped = if let Ok(stripped) = file_path.strip_prefix(".") {
-                    stripped.to_path_buf()
-                } else {
-                    file_path.to_path_buf()
-                };
-
-                // Calculate hash
-                let hash = hash_file(&file_path_stripped)?;
-
-                // Check if tracked and hash matches
-                if let Some(tracked) = tracked_map.get(&file_path_stripped.to_string_lossy().to_string()) {
-                    if tracked.hash.as_deref() == Some(&hash) {
-                        // File unchanged, skip
-                        continue;
-                    }
-                }
-
-                add_file_to_db(&db, &ignore_patterns, &file_path_stripped)?;
-            }
+            visit_dirs(path, &ignore_rules, &mut files)?;
+            candidates.extend(files.iter().map(|file_path| strip_dot(file_path)));
         } else {
-            let path_stripped = if let Ok(stripped) = path.strip_prefix(".") {
-                stripped.to_path_buf()
-            } else {
-                path.to_path_buf()
-            };
-
-            // Calculate hash
-            let hash = hash_file(&path_stripped)?;
-
-            // Check if tracked and hash matches
-            if let Some(tracked) = tracked_map.get(&path_stripped.to_string_lossy().to_string()) {
-                if tracked.hash.as_deref() == Some(&hash) {
-                    // File unchanged, skip
-                    continue;
-                }
-            }
+            candidates.insert(strip_dot(path));
+        }
+    }
 
-            add_file_to_db(&db, &ignore_patterns, &path_stripped)?;
+    // Skip files whose stat matches the cache; snapshot the rest into the object store in parallel
+    let mut to_store = Vec::new();
+    for path in candidates {
+        let stat = file_stat(&path)?;
+        let tracked = tracked_map.get(&path.to_string_lossy().to_string())
+            .filter(|t| t.status.as_deref() != Some("deleted"));
+        if !refresh && tracked.and_then(|t| cached_hash(t, &stat)).is_some() {
+            continue;
+        }
+        to_store.push((path, stat, tracked));
+    }
+    let algorithm = db.hash_algorithm()?;
+    let stored: Vec<Result<(String, u64)>> = to_store.par_iter()
+        .map(|(path, _, _)| store_object(path, algorithm))
+        .collect();
+
+    for ((path, stat, tracked), result) in to_store.iter().zip(stored) {
+        let (hash, size) = result?;
+        if let Some(tracked) = tracked
+            && tracked.hash.as_deref() == Some(hash.as_str())
+        {
+            // File unchanged, only refresh its stat
+            db.set_stat(&tracked.path, stat.size, stat.last_modified, stat.inode, stat.ctime)?;
+            continue;
         }
+        stage_stored_file(&db, path, stat, &hash, size)?;
     }
 
     // After processing provided paths, check all tracked files to see if any are missing locally and mark them deleted
@@ -379,300 +611,1543 @@ pub async fn process_add(paths: &[PathBuf]) -> anyhow::Result<()> {
         let local_path = Path::new(".").join(tracked_path);
         if !local_path.exists() {
             let stripped = if let Ok(s) = local_path.strip_prefix(".") { s.to_path_buf() } else { local_path };
-            add_file_to_db(&db, &ignore_patterns, &stripped)?; // will mark deleted
+            add_file_to_db(&db, Some(&ignore_rules), &stripped)?; // will mark deleted
         }
     }
 
     Ok(())
 }
 
+/// Print the given paths that are ignored; with `verbose`, also the rule that decided each one,
+/// as `<source>:<line>:<pattern>\t<path>` like `git check-ignore -v`.
+/// With a remote name, that remote's exclude list is applied too, as push does.
+pub async fn process_check_ignore(paths: &[PathBuf], verbose: bool, non_matching: bool, remote_name: Option<&str>) -> Result<()> {
+    let ignore_rules = match remote_name {
+        Some(name) => {
+            let config = get_config_detail(Some(name))?
+                .ok_or_else(|| anyhow::anyhow!("No remote named {} is configured", name))?;
+            load_push_rules(&config, name)?
+        }
+        None => load_scuttleignore()?,
+    };
+    for path in paths {
+        let found = ignore_rules.matched(path, path.is_dir())?;
+        match found {
+            Some(found) if verbose => {
+                println!("{}", found.describe(path));
+            }
+            Some(found) if found.is_ignored() => println!("{}", path.display()),
+            _ if verbose && non_matching => println!("::\t{}", path.display()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 pub async fn process_commit(message: &str) -> anyhow::Result<()> {
     let db = ScuttleDb::new(&std::path::PathBuf::from(".scuttle/scuttle.db"))?;
-    db.commit(message)?;
-    println!("Committed with message: {}", message);
+    let commit_id = db.commit(message)?;
+    println!("Committed {} with message: {}", &commit_id[..12], message);
     Ok(())
 }
 
-pub async fn process_push(remote_name: Option<&str>) -> anyhow::Result<()> {
-    // Minimal scaffold: verify config exists and print a message.
-    let confug_data = get_config_detail(remote_name)?;
-    if confug_data.is_none() {
-        return Err(anyhow::anyhow!("No configuration found. Please run init first."));
+/// Expand path arguments (files or directories) to the matching `candidates`.
+fn expand_path_specs<'a>(specs: &[PathBuf], candidates: impl Iterator<Item = &'a String> + Clone) -> Result<BTreeSet<String>> {
+    let mut matched = BTreeSet::new();
+    for spec in specs {
+        let spec = spec.to_string_lossy().replace('\\', "/");
+        let spec = spec.trim_start_matches("./").trim_end_matches('/');
+        let before = matched.len();
+        for candidate in candidates.clone() {
+            if spec.is_empty() || spec == "." || candidate == spec || candidate.starts_with(&format!("{}/", spec)) {
+                matched.insert(candidate.clone());
+            }
+        }
+        if matched.len() == before {
+            return Err(anyhow::anyhow!("Path did not match any file: {}", spec));
+        }
     }
-    let config = confug_data.unwrap();
-    let remote_server = config.get("remote_name")
-        .and_then(|n| n.as_str())
-        .map(|s| s.to_string())
-        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
-    println!("Starting push for remote: {}", remote_server);
+    Ok(matched)
+}
 
-    // Resolve remote root folder by name (best-effort)
-    // Determine remote folder name by checking the parent of the current directory
-    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let folder_name = current_dir.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&remote_server);
-    println!("Using remote root folder name: {}", folder_name);
-    let remote_root_folder = match find_folder_by_name(folder_name, &remote_server).await {
-        Ok(Some(id)) => {
-            println!("Found remote root folder id: {}", id);
-            Some(id)
+/// Paths among `paths` whose working copy has changes the DB does not know about,
+/// plus, if `include_staged`, paths with staged changes that are not committed yet.
+fn find_uncommitted(db: &ScuttleDb, paths: &BTreeSet<String>, include_staged: bool) -> Result<Vec<String>> {
+    let tracked: HashMap<String, TrackedFile> = db.get_tracked_files()?
+        .into_iter()
+        .map(|f| (f.path.clone(), f))
+        .collect();
+    let algorithm = db.hash_algorithm()?;
+    let mut uncommitted = Vec::new();
+    for path in paths {
+        let row = tracked.get(path);
+        if include_staged && row.is_some_and(|f| f.status.as_deref() != Some("committed")) {
+            uncommitted.push(path.clone());
+            continue;
         }
-        Ok(None) => {
-            println!("Remote root folder not found by name {}. Initial upload expected.", folder_name);
-            None
+        let local_path = Path::new(".").join(path);
+        if local_path.is_file() {
+            let recorded = row.filter(|f| f.status.as_deref() != Some("deleted")).and_then(|f| f.hash.clone());
+            if Some(hash_file(&local_path, algorithm)?) != recorded {
+                uncommitted.push(path.clone());
+            }
         }
-        Err(e) => {
-            println!("Error searching for remote folder: {}", e);
-            None
+    }
+    Ok(uncommitted)
+}
+
+/// Make the working copy of each path match `target` (absent means deleted).
+/// Returns the paths that actually changed.
+fn write_working_tree(paths: &BTreeSet<String>, target: &BTreeMap<String, String>, algorithm: HashAlgorithm) -> Result<Vec<String>> {
+    let mut changed = Vec::new();
+    for path in paths {
+        let local_path = Path::new(".").join(path);
+        match target.get(path) {
+            Some(hash) => {
+                if local_path.is_file() && &hash_file(&local_path, algorithm)? == hash {
+                    continue;
+                }
+                restore_object(hash, &local_path)?;
+                println!("Restored {}", path);
+            }
+            None => {
+                if !local_path.exists() {
+                    continue;
+                }
+                fs::remove_file(&local_path).with_context(|| format!("Failed to delete {}", path))?;
+                println!("Deleted {}", path);
+            }
         }
+        changed.push(path.clone());
+    }
+    Ok(changed)
+}
+
+/// Check out `rev` into the working tree, or only `paths` of it. The result is staged,
+/// so the next commit records it on top of the current history.
+pub async fn process_checkout(rev: &str, paths: &[PathBuf], force: bool) -> Result<()> {
+    let db = ScuttleDb::new(Path::new(".scuttle/scuttle.db"))?;
+    let commit_id = db.resolve_commit(rev)?;
+    let tree: BTreeMap<String, String> = db.commit_tree(&commit_id)?
+        .into_iter()
+        .map(|entry| (entry.path, entry.hash))
+        .collect();
+
+    let affected = if paths.is_empty() {
+        // The whole tree: files of the commit plus tracked files it did not have
+        let mut all: BTreeSet<String> = tree.keys().cloned().collect();
+        all.extend(db.get_tracked_files()?
+            .into_iter()
+            .filter(|f| f.status.as_deref() != Some("deleted"))
+            .map(|f| f.path));
+        all
+    } else {
+        expand_path_specs(paths, tree.keys())?
     };
 
-    // If we have a remote root folder, look for scuttle.db inside it
-    if let Some(root_id) = remote_root_folder {
-        // Prefer `.scuttle/scuttle.db` inside the project root folder on remote
-        let scuttle_folder_id = match find_file_in_folder(".scuttle", &root_id, &remote_server).await {
-            Ok(Some(id)) => Some(id),
-            Ok(None) => None,
-            Err(e) => {
-                println!("Error searching for remote .scuttle folder: {}", e);
-                None
-            }
-        };
+    let uncommitted = find_uncommitted(&db, &affected, true)?;
+    if !uncommitted.is_empty() && !force {
+        return Err(anyhow::anyhow!("Uncommitted changes would be overwritten: {}. Commit them or use --force.", uncommitted.join(", ")));
+    }
+
+    let changed = write_working_tree(&affected, &tree, db.hash_algorithm()?)?;
+    for path in &changed {
+        add_file_to_db(&db, None, Path::new(path))?;
+    }
+    println!("Checked out {} file(s) from {}. The changes are staged; commit them to record this state.", changed.len(), &commit_id[..12]);
+    Ok(())
+}
+
+/// Restore `paths` in the working tree to their staged content, or with `committed`
+/// to the last commit (which also drops their staged changes).
+pub async fn process_restore(paths: &[PathBuf], committed: bool, force: bool) -> Result<()> {
+    let db = ScuttleDb::new(Path::new(".scuttle/scuttle.db"))?;
+    let tracked: HashMap<String, TrackedFile> = db.get_tracked_files()?
+        .into_iter()
+        .map(|f| (f.path.clone(), f))
+        .collect();
+
+    let source: BTreeMap<String, TreeEntry> = if committed {
+        let head = db.head()?.ok_or_else(|| anyhow::anyhow!("No commits yet"))?;
+        db.commit_tree(&head)?.into_iter().map(|entry| (entry.path.clone(), entry)).collect()
+    } else {
+        tracked.values()
+            .filter(|f| f.status.as_deref() != Some("deleted"))
+            .filter_map(|f| Some((f.path.clone(), TreeEntry {
+                path: f.path.clone(),
+                hash: f.hash.clone().filter(|h| !h.is_empty())?,
+                size: f.size,
+                last_modified: f.last_modified,
+            })))
+            .collect()
+    };
+
+    let selected = expand_path_specs(paths, source.keys())?;
+    let uncommitted = find_uncommitted(&db, &selected, committed)?;
+    if !uncommitted.is_empty() && !force {
+        return Err(anyhow::anyhow!("Local changes would be overwritten: {}. Use --force to discard them.", uncommitted.join(", ")));
+    }
 
-        let search_parent = scuttle_folder_id.as_deref().unwrap_or(&root_id);
-        match find_file_in_folder("scuttle.db", search_parent, &remote_server).await {
-             Ok(Some(file_id)) => {
-                 println!("Found remote scuttle.db with id {}. Downloading...", file_id);
-                 let dest = PathBuf::from(".scuttle/remote_scuttle.db.tmp");
-                 std::fs::create_dir_all(PathBuf::from(".scuttle")).ok();
-                 if let Err(e) = download_file_by_id(&file_id, &dest, &remote_server).await {
-                     println!("Failed to download remote scuttle.db: {}", e);
-                     return Err(anyhow::anyhow!("Failed to download remote DB"));
-                 }
-                 println!("Downloaded remote DB to {}", dest.display());
-
-                 // Compute diff between remote DB and local DB
-                 let local_db_path = PathBuf::from(".scuttle/scuttle.db");
-                 if !local_db_path.exists() {
-                     println!("Local scuttle DB not found at {}", local_db_path.display());
-                     return Err(anyhow::anyhow!("Local DB missing"));
-                 }
-
-                 match ScuttleDb::diff_dbs(&dest, &local_db_path) {
-                     Ok((added, modified, deleted)) => {
-                         println!("Diff results - added: {}, modified: {}, deleted: {}", added.len(), modified.len(), deleted.len());
-                         println!("Added: {:?}\nModified: {:?}\nDeleted: {:?}", added, modified, deleted);
-                         // Apply deltas: deletes first
-                        // Helper: find remote file id by path under root_id
-                        async fn find_remote_id_by_path(root_id: &str, rel_path: &str, remote_server: &str) -> Option<String> {
-                            // Traverse folders
-                            let mut parent = root_id.to_string();
-                            let path = rel_path.replace("\\", "/");
-                            let comps: Vec<&str> = path.split('/').collect();
-                            if comps.is_empty() { return None; }
-                            for (i, comp) in comps.iter().enumerate() {
-                                if comp.is_empty() { continue; }
-                                let name = comp.to_string();
-                                if i == comps.len()-1 {
-                                    // last -> file; search in parent
-                                    match find_file_in_folder(&name, &parent, &remote_server.to_string()).await {
-                                        Ok(Some(id)) => return Some(id),
-                                        _ => return None,
-                                    }
-                                } else {
-                                    // folder
-                                    match find_file_in_folder(&name, &parent, &remote_server.to_string()).await {
-                                        Ok(Some(id)) => parent = id,
-                                        _ => return None,
-                                    }
-                                }
-                            }
-                            None
-                        }
-
-                        // Execute deletes
-                        for path in &deleted {
-                            println!("Deleting remote: {}", path);
-                            if let Some(remote_id) = find_remote_id_by_path(&root_id, path, &remote_server).await {
-                                if let Err(e) = delete_file_by_id(&remote_id, &remote_server).await {
-                                    println!("Failed to delete {}: {}", path, e);
-                                } else {
-                                    println!("Deleted remote {}", path);
-                                }
-                            } else {
-                                println!("Remote file not found for deletion: {}", path);
-                            }
-                        }
-
-                        // Upload added and modified (treat both similarly)
-                        for path in added.iter().chain(modified.iter()) {
-                            let local_path = Path::new(".").join(path);
-                            if !local_path.exists() {
-                                println!("Local file missing for upload: {}", path);
-                                continue;
-                            }
-                            // Ensure remote parent folders exist under project root
-                            let parent_dir = Path::new(path).parent().map(|p| p.to_string_lossy().to_string());
-                            let target_parent = if let Some(dir) = parent_dir {
-                                if dir.is_empty() || dir == "." {
-                                    root_id.clone()
-                                } else {
-                                    match crate::google_drive_api_client::ensure_remote_path(&root_id, &dir, &remote_server).await {
-                                        Ok(id) => id,
-                                        Err(e) => {
-                                            println!("Failed to ensure remote dir {}: {}. Uploading to root.", dir, e);
-                                            root_id.clone()
-                                        }
-                                    }
-                                }
-                            } else { root_id.clone() };
-
-                            println!("Uploading {} to remote...", path);
-                            match upload_file_with_parent(&local_path, Some(&target_parent), &remote_server).await {
-                                Ok(id) => println!("Uploaded {} as id {}", path, id),
-                                Err(e) => println!("Failed to upload {}: {}", path, e),
-                            }
-                        }
-
-                        // Safe DB swap: find existing scuttle.db id first, upload local DB, then delete old
-                        println!("Preparing DB swap: locating existing scuttle.db (if any)...");
-                        let scuttle_folder_id = match crate::google_drive_api_client::ensure_remote_path(&root_id, ".scuttle", &remote_server).await {
-                            Ok(id) => id,
-                            Err(e) => {
-                                println!("Failed to ensure remote .scuttle folder: {}. Aborting DB swap.", e);
-                                return Err(anyhow::anyhow!("Failed to ensure remote .scuttle"));
-                            }
-                        };
-                        // capture old id before upload
-                        let old_scuttle_id = match find_file_in_folder("scuttle.db", &scuttle_folder_id, &remote_server).await {
-                            Ok(Some(id)) => Some(id),
-                            _ => None,
-                        };
-
-                        // Upload local DB (this will create a new scuttle.db in the folder)
-                        println!("Uploading local scuttle DB...");
-                        match upload_file_with_parent(&local_db_path, Some(&scuttle_folder_id), &remote_server).await {
-                            Ok(new_id) => {
-                                println!("Uploaded new scuttle DB as id {}", new_id);
-                                // delete old if present and different from new
-                                if let Some(old_id) = old_scuttle_id {
-                                    if old_id != new_id {
-                                        if let Err(e) = delete_file_by_id(&old_id, &remote_server).await {
-                                            println!("Failed to delete old remote scuttle.db: {}", e);
-                                        } else {
-                                            println!("Deleted old remote scuttle.db (id={})", old_id);
-                                        }
-                                    } else {
-                                        println!("Old and new scuttle.db IDs are same; no delete needed");
-                                    }
-                                }
-                                println!("DB swap completed.");
-                            }
-                            Err(e) => println!("Failed to upload new scuttle DB: {}", e),
-                        }
-
-                        println!("Push apply complete.");
-                     }
-                     Err(e) => {
-                         println!("Failed to compute DB diff: {}", e);
-                         return Err(anyhow::anyhow!("DB diff failed"));
-                     }
-                 }
-
-              }
-             Ok(None) => {
-                println!("No remote scuttle.db found in remote root or .scuttle folder. Initial upload required.");
-                // TODO: implement initial upload: create .scuttle folder on remote and upload files
-             }
-             Err(e) => {
-                 println!("Error searching for scuttle.db in remote root: {}", e);
-                 return Err(anyhow::anyhow!("Failed during remote DB lookup"));
-             }
-         }
-     } else {
-        println!("Remote root not found; creating remote root folder and performing initial upload.");
-
-        // Create remote root folder with the remote_server name
-        let created_root = match create_folder(&folder_name, None, &remote_server).await {
-            Ok(id) => {
-                println!("Created remote root folder '{}' with id {}", remote_server, id);
-                id
+    let target: BTreeMap<String, String> = selected.iter()
+        .filter_map(|path| source.get(path).map(|entry| (path.clone(), entry.hash.clone())))
+        .collect();
+    let changed = write_working_tree(&selected, &target, db.hash_algorithm()?)?;
+
+    if committed {
+        // Drop staged changes: the rows go back to what the commit recorded
+        for path in &selected {
+            let entry = &source[path];
+            db.set_committed_file(&TrackedFile {
+                path: path.clone(),
+                hash: Some(entry.hash.clone()),
+                last_modified: entry.last_modified,
+                status: Some("committed".to_string()),
+                remote_id: tracked.get(path).and_then(|f| f.remote_id.clone()),
+                size: entry.size,
+                inode: None,
+                ctime: None,
+            })?;
+        }
+    }
+    println!("Restored {} file(s).", changed.len());
+    Ok(())
+}
+
+/// Where one side of a diff takes a file's content from.
+enum DiffSource {
+    Working,
+    Object(String),
+}
+
+/// Tracked, non-deleted files of the DB as path -> stored content.
+fn db_sources(db: &ScuttleDb) -> Result<BTreeMap<String, DiffSource>> {
+    Ok(db.get_tracked_files()?
+        .into_iter()
+        .filter(|f| f.status.as_deref() != Some("deleted"))
+        .filter_map(|f| Some((f.path, DiffSource::Object(f.hash.filter(|h| !h.is_empty())?))))
+        .collect())
+}
+
+fn tree_sources(db: &ScuttleDb, commit_id: &str) -> Result<BTreeMap<String, DiffSource>> {
+    Ok(db.commit_tree(commit_id)?
+        .into_iter()
+        .map(|entry| (entry.path, DiffSource::Object(entry.hash)))
+        .collect())
+}
+
+/// The hash of one side of a diff, without reading the file when the stat cache
+/// still vouches for the staged hash. `None` if the file does not exist on that side.
+fn diff_side_hash(path: &str, source: Option<&DiffSource>, tracked: &HashMap<String, TrackedFile>, algorithm: HashAlgorithm) -> Result<Option<String>> {
+    match source {
+        None => Ok(None),
+        Some(DiffSource::Object(hash)) => Ok(Some(hash.clone())),
+        Some(DiffSource::Working) => {
+            let local_path = Path::new(".").join(path);
+            if !local_path.is_file() {
+                return Ok(None);
             }
-            Err(e) => {
-                println!("Failed to create remote root folder: {}", e);
-                return Err(anyhow::anyhow!("Failed to create remote root folder"));
+            let stat = file_stat(&local_path)?;
+            if let Some(hash) = tracked.get(path).and_then(|f| cached_hash(f, &stat)).filter(|h| !h.is_empty()) {
+                return Ok(Some(hash));
             }
+            Ok(Some(hash_file(&local_path, algorithm)?))
+        }
+    }
+}
+
+/// The content of one side of a diff with the given `hash`. Binary and large files
+/// are only sniffed, not read.
+fn load_diff_content(path: &str, source: Option<&DiffSource>, hash: Option<&str>) -> Result<DiffContent> {
+    use std::io::Read;
+
+    let (Some(source), Some(hash)) = (source, hash) else {
+        return Ok(DiffContent::Missing);
+    };
+    let file = match source {
+        DiffSource::Working => Path::new(".").join(path),
+        DiffSource::Object(_) => object_path(hash),
+    };
+    if !file.exists() {
+        return Ok(DiffContent::Unavailable(hash.to_string()));
+    }
+    let size = fs::metadata(&file)?.len();
+    if size > MAX_DIFF_BYTES {
+        return Ok(DiffContent::TooLarge { size, hash: hash.to_string() });
+    }
+    // Like git, a NUL byte near the start marks the file as binary
+    let mut head = Vec::new();
+    File::open(&file)?.take(8000).read_to_end(&mut head)?;
+    if head.contains(&0) {
+        return Ok(DiffContent::Binary { size, hash: hash.to_string() });
+    }
+    match String::from_utf8(fs::read(&file)?) {
+        Ok(text) => Ok(DiffContent::Text(text)),
+        Err(_) => Ok(DiffContent::Binary { size, hash: hash.to_string() }),
+    }
+}
+
+/// Compare the working tree against the staged state (default), the staged state
+/// against the last commit (`staged`), either of them against `commit`, or the
+/// local DB against the remote one (`remote`).
+pub async fn process_diff(paths: &[PathBuf], staged: bool, commit: Option<&str>, remote: bool, remote_name: Option<&str>) -> Result<()> {
+    let local_db_path = PathBuf::from(".scuttle/scuttle.db");
+    let db = ScuttleDb::new(&local_db_path)?;
+
+    let (old, new) = if remote {
+        diff_sources_against_remote(&db, &local_db_path, remote_name).await?
+    } else {
+        let base_commit = match commit {
+            Some(rev) => Some(db.resolve_commit(rev)?),
+            None if staged => db.head()?,
+            None => None,
+        };
+        let old = match &base_commit {
+            Some(commit_id) => tree_sources(&db, commit_id)?,
+            None if staged => BTreeMap::new(),
+            None => db_sources(&db)?,
         };
+        let new = if staged {
+            db_sources(&db)?
+        } else {
+            // The working copy of every path either side knows about
+            let mut working: BTreeMap<String, DiffSource> = old.keys().map(|p| (p.clone(), DiffSource::Working)).collect();
+            working.extend(db_sources(&db)?.into_keys().map(|p| (p, DiffSource::Working)));
+            working
+        };
+        (old, new)
+    };
 
-        // Load local tracked files from DB and upload each file that exists locally into the created folder
-        let db_path = Path::new(".scuttle/scuttle.db");
-        let tracked_files = ScuttleDb::load_tracked_files(db_path)?;
-        let mut uploaded = 0usize;
-        let mut skipped = 0usize;
-        for tf in tracked_files {
-            // Skip deleted entries
-            if let Some(status) = &tf.status {
-                if status == "deleted" {
-                    skipped += 1;
-                    continue;
-                }
-            }
+    let algorithm = db.hash_algorithm()?;
+    let all_paths: BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+    let selected = if paths.is_empty() {
+        all_paths
+    } else {
+        expand_path_specs(paths, all_paths.iter())?
+    };
 
-            let local_path = PathBuf::from(".").join(&tf.path);
-            if local_path.exists() {
-                println!("Uploading {}...", tf.path);
-                // Determine parent folder path on remote and ensure it exists
-                let parent_dir = Path::new(&tf.path).parent().map(|p| p.to_string_lossy().to_string());
-                let target_parent_id = if let Some(dir) = parent_dir {
-                    if dir.is_empty() || dir == "." {
-                        created_root.clone()
-                    } else {
-                        match crate::google_drive_api_client::ensure_remote_path(&created_root, &dir, &remote_server).await {
-                            Ok(id) => id,
-                            Err(e) => {
-                                println!("Failed to ensure remote dir {}: {}", dir, e);
-                                created_root.clone()
-                            }
-                        }
-                    }
-                } else {
-                    created_root.clone()
-                };
+    // Hashes first, so content is only loaded for the paths that changed
+    let tracked: HashMap<String, TrackedFile> = db.get_tracked_files()?
+        .into_iter()
+        .map(|f| (f.path.clone(), f))
+        .collect();
+    for path in &selected {
+        let old_hash = diff_side_hash(path, old.get(path), &tracked, algorithm)?;
+        let new_hash = diff_side_hash(path, new.get(path), &tracked, algorithm)?;
+        if old_hash == new_hash {
+            continue;
+        }
+        let old_content = load_diff_content(path, old.get(path), old_hash.as_deref())?;
+        let new_content = load_diff_content(path, new.get(path), new_hash.as_deref())?;
+        print_file_diff(path, &old_content, &new_content);
+    }
+    Ok(())
+}
 
-                let res = upload_file_with_parent(&local_path, Some(&target_parent_id), &remote_server).await;
-                if res.is_ok() {
-                    uploaded += 1;
-                } else {
-                    println!("Failed to upload {}", tf.path);
+/// Remote DB (old) vs local DB (new), as the next push would apply it. Remote
+/// versions missing from the object store are downloaded into it first.
+async fn diff_sources_against_remote(db: &ScuttleDb, local_db_path: &Path, remote_name: Option<&str>) -> Result<(BTreeMap<String, DiffSource>, BTreeMap<String, DiffSource>)> {
+    let config = load_remote_config(remote_name)?;
+    let remote_server = config.get("remote_name")
+        .and_then(|n| n.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
+    let remote = get_server_client(&config).await?;
+    let remote = remote.as_ref();
+
+    let (folder_name, root_id) = find_remote_root(remote, &remote_server).await;
+    let root_id = root_id
+        .ok_or_else(|| anyhow::anyhow!("Remote repo '{}' not found on {}", folder_name, remote_server))?;
+    let file_id = find_remote_db(remote, &root_id).await?
+        .ok_or_else(|| anyhow::anyhow!("No scuttle.db found in remote repo '{}'", folder_name))?;
+    let remote_db_path = download_remote_db(remote, &file_id).await?;
+
+    let (added, modified, deleted) = ScuttleDb::diff_dbs(&remote_db_path, local_db_path)?;
+    let remote_db = ScuttleDb::new(&remote_db_path)?;
+    let remote_ids = remote_db.remote_ids(&root_id)?;
+    let remote_sources = db_sources(&remote_db)?;
+    let local_sources = db_sources(db)?;
+    drop(remote_db);
+    fs::remove_file(&remote_db_path).ok();
+
+    let mut old = BTreeMap::new();
+    let mut new = BTreeMap::new();
+    for path in modified.iter().chain(&deleted) {
+        if let Some(DiffSource::Object(hash)) = remote_sources.get(path) {
+            if !object_path(hash).exists() {
+                let tmp = PathBuf::from(".scuttle/diff_remote.tmp");
+                let remote_id = remote_ids.get(path).map(String::as_str);
+                let algorithm = db.hash_algorithm()?;
+                match download_remote_path(remote, &root_id, path, remote_id, Some((hash, algorithm)), &tmp).await {
+                    Ok(()) => {
+                        store_object(&tmp, algorithm)?;
+                    }
+                    Err(e) => println!("Could not fetch remote version of {}: {}", path, e),
                 }
-            } else {
-                println!("Local file missing, skipping: {}", tf.path);
-                skipped += 1;
+                fs::remove_file(&tmp).ok();
             }
+            old.insert(path.clone(), DiffSource::Object(hash.clone()));
         }
-
-        // Finally, upload the scuttle DB itself into the created root
-        let db_path = PathBuf::from(".scuttle/scuttle.db");
-        if db_path.exists() {
-            println!("Uploading scuttle DB...");
-            // Ensure remote has a `.scuttle` folder and upload DB there
-            let scuttle_folder_id = match crate::google_drive_api_client::ensure_remote_path(&created_root, ".scuttle", &remote_server).await {
-                Ok(id) => id,
-                Err(e) => {
-                    println!("Failed to ensure remote .scuttle folder: {}. Falling back to root.", e);
-                    created_root.clone()
-                }
-            };
-            let res = upload_file_with_parent(&db_path, Some(&scuttle_folder_id), &remote_server).await;
-            if res.is_ok() { println!("Uploaded remote scuttle.db"); } else { println!("Failed to upload scuttle.db"); }
-        } else {
-            println!("Local scuttle DB not found at {}", db_path.display());
+    }
+    for path in added.iter().chain(&modified) {
+        if let Some(DiffSource::Object(hash)) = local_sources.get(path) {
+            new.insert(path.clone(), DiffSource::Object(hash.clone()));
         }
-
-        println!("Initial upload completed: uploaded={}, skipped={}", uploaded, skipped);
     }
+    Ok((old, new))
+}
 
-    Ok(())
+/// Parse a `--since`/`--until` value: `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS]` (local
+/// time) or RFC 3339. A bare date means the start of that day, or its end if `end_of_day`.
+fn parse_date_arg(value: &str, end_of_day: bool) -> Result<i64> {
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.timestamp());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            if end_of_day { date.and_hms_opt(23, 59, 59) } else { date.and_hms_opt(0, 0, 0) }
+        })
+        .ok_or_else(|| anyhow::anyhow!("Invalid date '{}': use YYYY-MM-DD, 'YYYY-MM-DD HH:MM[:SS]' or RFC 3339", value))?;
+    Local.from_local_datetime(&naive).earliest()
+        .map(|dt| dt.timestamp())
+        .ok_or_else(|| anyhow::anyhow!("Invalid local time: {}", value))
 }
 
+pub async fn process_log(path: Option<&str>, since: Option<&str>, until: Option<&str>, max_count: Option<usize>, stat: bool) -> Result<()> {
+    use chrono::{Local, TimeZone};
+
+    let db = ScuttleDb::new(&PathBuf::from(".scuttle/scuttle.db"))?;
+    let Some(head) = db.head()? else {
+        println!("No commits yet.");
+        return Ok(());
+    };
+    let since = since.map(|s| parse_date_arg(s, false)).transpose()?;
+    let until = until.map(|s| parse_date_arg(s, true)).transpose()?;
+    let path = path.map(|p| p.trim_start_matches("./").to_string());
+
+    let mut commits = db.history(&head)?;
+    if let Some(legacy) = db.legacy_head()?
+        && !db.is_ancestor(&legacy, &head)? {
+        commits.extend(db.history(&legacy)?);
+    }
+
+    let mut shown = 0usize;
+    for commit in commits {
+        if max_count.is_some_and(|n| shown >= n) {
+            break;
+        }
+        if since.is_some_and(|t| commit.timestamp < t) || until.is_some_and(|t| commit.timestamp > t) {
+            continue;
+        }
+        let changes = db.commit_changes(&commit.id)?;
+        if let Some(path) = &path
+            && !changes.iter().any(|(p, _)| p == path) {
+            continue;
+        }
+        shown += 1;
+
+        let count = |status: &str| changes.iter().filter(|(_, s)| s == status).count();
+        let date = Local.timestamp_opt(commit.timestamp, 0).single()
+            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S %z").to_string())
+            .unwrap_or_else(|| commit.timestamp.to_string());
+
+        println!("commit {}", commit.id);
+        if let (Some(parent), Some(merge_parent)) = (&commit.parent, &commit.merge_parent) {
+            println!("Merge:  {} {}", &parent[..12], &merge_parent[..12]);
+        }
+        println!("Date:   {}", date);
+        println!();
+        println!("    {}", commit.message);
+        println!();
+        println!("    {} added, {} modified, {} deleted", count("added"), count("modified"), count("deleted"));
+        if stat {
+            for (changed_path, status) in &changes {
+                let marker = match status.as_str() {
+                    "added" => "A",
+                    "modified" => "M",
+                    "deleted" => "D",
+                    _ => "?",
+                };
+                println!("    {}  {}", marker, changed_path);
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Upload a local file into `rel_path` under `root_id`, creating parent folders as needed.
+/// Folder ids are cached in the DB; a stale cached folder is looked up again.
+async fn upload_to_path(remote: &dyn RemoteStorage, db: &ScuttleDb, root_id: &str, rel_path: &str, local_path: &Path) -> Result<String> {
+    let rel = Path::new(rel_path);
+    let name = rel.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", rel_path))?;
+    let parent_dir = rel.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+    if parent_dir.is_empty() || parent_dir == "." {
+        return remote.upload(local_path, name, Some(root_id)).await;
+    }
+
+    if let Some(parent_id) = db.get_remote_folder_id(&parent_dir)? {
+        match remote.upload(local_path, name, Some(&parent_id)).await {
+            Err(e) if is_not_found(&e) => {
+                println!("Cached remote folder for {} is stale; looking it up again.", parent_dir);
+                db.clear_remote_folder_id(&parent_dir)?;
+            }
+            other => return other,
+        }
+    }
+
+    let parent_id = match remote.ensure_path(root_id, &parent_dir).await {
+        Ok(id) => {
+            db.set_remote_folder_id(&parent_dir, &id)?;
+            id
+        }
+        Err(e) => {
+            println!("Failed to ensure remote dir {}: {}. Uploading to root.", parent_dir, e);
+            root_id.to_string()
+        }
+    };
+    remote.upload(local_path, name, Some(&parent_id)).await
+}
+
+/// Replace the content of `rel_path` on the remote in place, locating it by its
+/// recorded id or by path. Uploads it as a new file if it is not on the remote.
+async fn update_remote_path(remote: &dyn RemoteStorage, db: &ScuttleDb, root_id: &str, rel_path: &str, remote_id: Option<&str>, local_path: &Path) -> Result<String> {
+    if let Some(id) = remote_id {
+        match remote.update(id, local_path).await {
+            Ok(()) => return Ok(id.to_string()),
+            Err(e) if is_not_found(&e) => println!("Recorded id for {} is stale; looking it up by path.", rel_path),
+            Err(e) => return Err(e),
+        }
+    }
+    match remote.find_by_path(root_id, rel_path).await? {
+        Some(id) => remote.update(&id, local_path).await.map(|_| id),
+        None => upload_to_path(remote, db, root_id, rel_path, local_path).await,
+    }
+}
+
+/// Delete `rel_path` on the remote, by its recorded id when there is one and by
+/// path lookup otherwise. Returns false if the file was not found.
+async fn delete_remote_path(remote: &dyn RemoteStorage, root_id: &str, rel_path: &str, remote_id: Option<&str>) -> Result<bool> {
+    if let Some(id) = remote_id {
+        match remote.delete(id).await {
+            Ok(()) => return Ok(true),
+            Err(e) if is_not_found(&e) => println!("Recorded id for {} is stale; looking it up by path.", rel_path),
+            Err(e) => return Err(e),
+        }
+    }
+    match remote.find_by_path(root_id, rel_path).await? {
+        Some(id) => remote.delete(&id).await.map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Download `rel_path` from the remote, by its recorded id when there is one and
+/// by path lookup otherwise. `expected` is the hash the remote DB records for it.
+async fn download_remote_path(remote: &dyn RemoteStorage, root_id: &str, rel_path: &str, remote_id: Option<&str>, expected: Option<(&str, HashAlgorithm)>, local_path: &Path) -> Result<()> {
+    if let Some(id) = remote_id {
+        match download_to_path(remote, id, local_path, expected).await {
+            Err(e) if is_not_found(&e) => println!("Recorded id for {} is stale; looking it up by path.", rel_path),
+            other => return other,
+        }
+    }
+    match remote.find_by_path(root_id, rel_path).await? {
+        Some(id) => download_to_path(remote, &id, local_path, expected).await,
+        None => Err(anyhow::anyhow!("not found on remote")),
+    }
+}
+
+/// Locate `.scuttle/scuttle.db` (or a bare `scuttle.db`) under the remote root.
+async fn find_remote_db(remote: &dyn RemoteStorage, root_id: &str) -> Result<Option<String>> {
+    // Prefer `.scuttle/scuttle.db` inside the project root folder on remote
+    let scuttle_folder_id = match remote.find_in_folder(".scuttle", root_id).await {
+        Ok(id) => id,
+        Err(e) => {
+            println!("Error searching for remote .scuttle folder: {}", e);
+            None
+        }
+    };
+    let search_parent = scuttle_folder_id.as_deref().unwrap_or(root_id);
+    remote.find_in_folder("scuttle.db", search_parent).await
+}
+
+/// The remote root folder is named after the local project directory.
+fn repo_folder_name(fallback: &str) -> String {
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    current_dir.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(fallback)
+        .to_string()
+}
+
+/// Resolve this repo's root folder on the remote: the recorded binding first, then
+/// a folder named after the project directory. Returns the folder name and id.
+async fn find_remote_root(remote: &dyn RemoteStorage, remote_server: &str) -> (String, Option<String>) {
+    if let Ok(Some(binding)) = get_remote_binding(remote_server)
+        && let Some(root_id) = binding.get("root_id").and_then(|r| r.as_str())
+    {
+        match remote.stat(root_id).await {
+            Ok(Some(entry)) if entry.is_folder => {
+                println!("Using recorded remote root folder id: {}", root_id);
+                return (entry.name, Some(root_id.to_string()));
+            }
+            _ => println!("Recorded remote root folder {} not found; searching by name.", root_id),
+        }
+    }
+
+    let folder_name = repo_folder_name(remote_server);
+    println!("Using remote root folder name: {}", folder_name);
+    let root_id = match remote.find_folder(&folder_name).await {
+        Ok(Some(id)) => {
+            println!("Found remote root folder id: {}", id);
+            Some(id)
+        }
+        Ok(None) => {
+            println!("Remote root folder not found by name {}. Initial upload expected.", folder_name);
+            None
+        }
+        Err(e) => {
+            println!("Error searching for remote folder: {}", e);
+            None
+        }
+    };
+    (folder_name, root_id)
+}
+
+/// Snapshot `db_path` as the state both sides agree on after a successful sync.
+/// `db_hash` is the hash of the DB as stored on the remote.
+/// Sync state compares DB files by SHA-256, whatever algorithm the repo uses for content.
+fn hash_db_file(path: &Path) -> Result<String> {
+    hash_file(path, HashAlgorithm::Sha256)
+}
+
+/// Hashes from two DBs are only comparable if both use the same algorithm.
+fn ensure_same_hash_algorithm(local_db_path: &Path, remote_db_path: &Path) -> Result<()> {
+    let local = ScuttleDb::new(local_db_path)?.hash_algorithm()?;
+    let remote = ScuttleDb::new(remote_db_path)?.hash_algorithm()?;
+    if local != remote {
+        return Err(anyhow::anyhow!("The remote repo uses {} hashes but this repo uses {}", remote.as_str(), local.as_str()));
+    }
+    Ok(())
+}
+
+fn record_synced_state(remote_server: &str, db_path: &Path, db_hash: &str) -> Result<()> {
+    let base_path = get_base_db_path(remote_server);
+    if let Some(parent) = base_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(db_path, &base_path).context("Failed to record synced DB snapshot")?;
+    set_synced_db_hash(remote_server, db_hash)
+}
+
+/// Whether the remote DB differs from the one recorded at the last sync.
+fn remote_moved(remote_server: &str, remote_db_hash: &str) -> Result<bool> {
+    let synced_hash = get_remote_binding(remote_server)?
+        .and_then(|b| b.get("synced_db_hash").and_then(|h| h.as_str()).map(|h| h.to_string()));
+    Ok(synced_hash.as_deref() != Some(remote_db_hash))
+}
+
+fn classify_sync_changes(remote_server: &str, local_db_path: &Path, remote_db_path: &Path) -> Result<SyncChanges> {
+    let base_path = get_base_db_path(remote_server);
+    let base = base_path.exists().then_some(base_path.as_path());
+    ScuttleDb::classify_changes(base, local_db_path, remote_db_path)
+}
+
+fn print_remote_changes(changes: &SyncChanges) {
+    println!("Remote has changed since the last sync:");
+    for path in &changes.both_changed {
+        println!("  both changed: {}", path);
+    }
+    for path in &changes.remote_only {
+        println!("  remote only:  {}", path);
+    }
+}
+
+/// Download the remote DB to `.scuttle/remote_scuttle.db.tmp` and return that path.
+async fn download_remote_db(remote: &dyn RemoteStorage, file_id: &str) -> Result<PathBuf> {
+    let dest = PathBuf::from(".scuttle/remote_scuttle.db.tmp");
+    std::fs::create_dir_all(PathBuf::from(".scuttle")).ok();
+    if let Err(e) = download_to_path(remote, file_id, &dest, None).await {
+        println!("Failed to download remote scuttle.db: {}", e);
+        return Err(anyhow::anyhow!("Failed to download remote DB"));
+    }
+    println!("Downloaded remote DB to {}", dest.display());
+    Ok(dest)
+}
+
+/// Where a download of `file_id` is written until it is complete and verified.
+///
+/// Inside a repo partial downloads live in `.scuttle/partial`, named after the
+/// expected content hash (or the remote id when none is known) and the target
+/// path, so a failed download resumes on the next attempt and never shows up in
+/// the working tree. Concurrent downloads of the same content to different paths
+/// each get their own partial file.
+fn partial_download_path(file_id: &str, expected_hash: Option<&str>, local_path: &Path) -> PathBuf {
+    let partial_dir = Path::new(PARTIAL_DOWNLOADS_DIR);
+    if partial_dir.parent().is_some_and(Path::is_dir) {
+        let content = match expected_hash {
+            Some(hash) => hash.to_string(),
+            None => format!("id-{}", hash_bytes(file_id.as_bytes())),
+        };
+        let target = hash_bytes(local_path.to_string_lossy().as_bytes());
+        return partial_dir.join(format!("{}-{}", content, &target[..16]));
+    }
+    let file_name = local_path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    local_path.with_file_name(format!(".{}.scuttle-tmp", file_name))
+}
+
+/// Download a remote file to `local_path` via a partial file, so an interrupted
+/// transfer never clobbers the working copy. When `expected` is given the content
+/// must hash to it; only a complete, verified file is renamed into place.
+async fn download_to_path(remote: &dyn RemoteStorage, file_id: &str, local_path: &Path, expected: Option<(&str, HashAlgorithm)>) -> Result<()> {
+    if let Some(parent) = local_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create local directory")?;
+    }
+    let partial = partial_download_path(file_id, expected.map(|(hash, _)| hash), local_path);
+    if let Some(parent) = partial.parent() {
+        fs::create_dir_all(parent).context("Failed to create partial download directory")?;
+    }
+    if let Err(e) = remote.download(file_id, &partial).await {
+        // Keep what arrived so the next attempt can resume, unless there is nothing to resume
+        if is_not_found(&e) {
+            fs::remove_file(&partial).ok();
+        }
+        return Err(e);
+    }
+    if let Some((hash, algorithm)) = expected
+        && hash_file(&partial, algorithm)? != hash
+    {
+        fs::remove_file(&partial).ok();
+        return Err(anyhow::anyhow!("downloaded content does not match the recorded hash {}", hash));
+    }
+    fs::rename(&partial, local_path).context("Failed to move downloaded file into place")?;
+    Ok(())
+}
+
+/// Upload the local DB into the remote `.scuttle` folder, replacing the previous copy
+/// only after the new one is in place.
+async fn swap_remote_db(remote: &dyn RemoteStorage, root_id: &str, local_db_path: &Path) -> Result<()> {
+    println!("Preparing DB swap: locating existing scuttle.db (if any)...");
+    let scuttle_folder_id = remote.ensure_path(root_id, ".scuttle").await
+        .context("Failed to ensure remote .scuttle folder")?;
+    // capture old id before upload
+    let old_scuttle_id = remote.find_in_folder("scuttle.db", &scuttle_folder_id).await.ok().flatten();
+
+    // Upload local DB (this will create a new scuttle.db in the folder)
+    println!("Uploading local scuttle DB...");
+    let new_id = remote.upload(local_db_path, "scuttle.db", Some(&scuttle_folder_id)).await
+        .context("Failed to upload new scuttle DB")?;
+    println!("Uploaded new scuttle DB as id {}", new_id);
+
+    // delete old if present and different from new
+    match old_scuttle_id {
+        Some(old_id) if old_id != new_id => {
+            if let Err(e) = remote.delete(&old_id).await {
+                println!("Failed to delete old remote scuttle.db: {}", e);
+            } else {
+                println!("Deleted old remote scuttle.db (id={})", old_id);
+            }
+        }
+        Some(_) => println!("Old and new scuttle.db IDs are same; no delete needed"),
+        None => {}
+    }
+    println!("DB swap completed.");
+    Ok(())
+}
+
+pub async fn process_push(remote_name: Option<&str>, force: bool, jobs: Option<usize>) -> anyhow::Result<()> {
+    if PushJournal::exists() {
+        return Err(anyhow::anyhow!("A previous push was interrupted. Run `scuttle push --continue` to finish it, or `scuttle push --abort` to undo it."));
+    }
+    let config = load_remote_config(remote_name)?;
+    let jobs = transfer_jobs(jobs, &config);
+    let remote_server = config.get("remote_name")
+        .and_then(|n| n.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
+    let remote = get_server_client(&config).await?;
+    let remote = remote.as_ref();
+    println!("Starting push for remote: {}", remote_server);
+
+    // Resolve remote root folder (recorded binding first, then by name)
+    let (folder_name, remote_root_folder) = find_remote_root(remote, &remote_server).await;
+
+    let local_db_path = PathBuf::from(".scuttle/scuttle.db");
+
+    // If we have a remote root folder, look for scuttle.db inside it
+    // Paths excluded for this remote are left out of the uploaded DB as well as the uploads
+    let push_rules = load_push_rules(&config, &remote_server)?;
+
+    let root_id = match remote_root_folder {
+        Some(root_id) => root_id,
+        None => {
+            // First push: create the root folder, named after the local project folder
+            let root_id = remote.mkdir(&folder_name, None).await.context("Failed to create remote root folder")?;
+            println!("Created remote root folder '{}' with id {}", folder_name, root_id);
+            root_id
+        }
+    };
+
+    let dest = match find_remote_db(remote, &root_id).await {
+        Ok(Some(file_id)) => {
+            println!("Found remote scuttle.db with id {}. Downloading...", file_id);
+            Some(download_remote_db(remote, &file_id).await?)
+        }
+        Ok(None) => {
+            println!("No remote scuttle.db found in remote root or .scuttle folder. Uploading every tracked file.");
+            None
+        }
+        Err(e) => {
+            println!("Error searching for scuttle.db in remote root: {}", e);
+            return Err(anyhow::anyhow!("Failed during remote DB lookup"));
+        }
+    };
+    // Hash before opening, since opening a DB may migrate it; empty when there is no remote DB yet
+    let remote_db_hash = match &dest {
+        Some(dest) => hash_db_file(dest)?,
+        None => String::new(),
+    };
+
+    // Compute diff between remote DB and local DB
+    if !local_db_path.exists() {
+        println!("Local scuttle DB not found at {}", local_db_path.display());
+        return Err(anyhow::anyhow!("Local DB missing"));
+    }
+
+    if let Some(dest) = &dest {
+        ensure_same_hash_algorithm(&local_db_path, dest)?;
+    }
+    let push_db_path = write_push_db(&local_db_path, &push_rules)?;
+
+    // Refuse to overwrite changes someone else pushed since our last sync
+    if let Some(dest) = &dest
+        && remote_moved(&remote_server, &remote_db_hash)?
+    {
+        let changes = classify_sync_changes(&remote_server, &push_db_path, dest)?;
+        if !changes.remote_only.is_empty() || !changes.both_changed.is_empty() {
+            print_remote_changes(&changes);
+            if !force {
+                return Err(anyhow::anyhow!("Remote has changed since the last sync. Run `scuttle pull` first, or push with --force to overwrite the remote changes."));
+            }
+            println!("--force given; overwriting remote changes.");
+        }
+    }
+
+    let diff = match &dest {
+        Some(dest) => ScuttleDb::diff_dbs(dest, &push_db_path),
+        // Nothing on the remote yet, so every tracked file is added
+        None => ScuttleDb::new(&push_db_path)?.get_tracked_files().map(|files| {
+            let added = files.into_iter().filter(|f| f.status.as_deref() != Some("deleted")).map(|f| f.path).collect();
+            (added, Vec::new(), Vec::new())
+        }),
+    };
+    let (added, modified, deleted) = match diff {
+        Ok(diff) => diff,
+        Err(e) => {
+            println!("Failed to compute DB diff: {}", e);
+            return Err(anyhow::anyhow!("DB diff failed"));
+        }
+    };
+    println!("Diff results - added: {}, modified: {}, deleted: {}", added.len(), modified.len(), deleted.len());
+    println!("Added: {:?}\nModified: {:?}\nDeleted: {:?}", added, modified, deleted);
+
+    // Ids and hashes of files the remote has, as recorded by whoever pushed last
+    let (remote_ids, remote_hashes) = match &dest {
+        Some(dest) => {
+            let remote_db = ScuttleDb::new(dest)?;
+            let remote_ids = remote_db.remote_ids(&root_id)?;
+            let remote_hashes: HashMap<String, Option<String>> = remote_db.get_tracked_files()?
+                .into_iter()
+                .map(|f| (f.path, f.hash))
+                .collect();
+            drop(remote_db);
+            fs::remove_file(dest).ok();
+            (remote_ids, remote_hashes)
+        }
+        None => (HashMap::new(), HashMap::new()),
+    };
+    let db = ScuttleDb::new(&local_db_path)?;
+    db.use_remote_root(&root_id)?;
+    // Upload committed snapshots rather than working files that may change mid-push
+    let local_hashes: HashMap<String, Option<String>> = db.get_tracked_files()?
+        .into_iter()
+        .map(|f| (f.path, f.hash))
+        .collect();
+    // Added files that already have an id were uploaded by an earlier push that did not finish
+    let pushed_ids = db.remote_ids(&root_id)?;
+
+    let planned = |op: PushOp, path: &String, remote_id: Option<&String>| PlannedOp {
+        op,
+        path: path.clone(),
+        old_hash: if op == PushOp::Upload { None } else { remote_hashes.get(path).cloned().flatten() },
+        new_hash: if op == PushOp::Delete { None } else { local_hashes.get(path).cloned().flatten() },
+        remote_id: remote_id.cloned(),
+        done: false,
+    };
+    let ops = deleted.iter().map(|p| planned(PushOp::Delete, p, remote_ids.get(p)))
+        .chain(added.iter().map(|p| planned(PushOp::Upload, p, pushed_ids.get(p))))
+        .chain(modified.iter().map(|p| planned(PushOp::Update, p, remote_ids.get(p))))
+        .collect();
+
+    // Write the plan, and the DB it leads to, before touching the remote
+    fs::rename(&push_db_path, JOURNAL_DB_PATH).context("Failed to save push DB")?;
+    let mut journal = PushJournal::begin(&remote_server, &root_id, &folder_name, &remote_db_hash, ops)?;
+    apply_push_journal(remote, &mut journal, &db, jobs).await?;
+    finish_push(remote, &journal).await
+}
+
+/// Apply the operations of `journal` that are not done yet, recording each one
+/// as it completes. Deletes go first; each phase runs up to `jobs` transfers at once.
+async fn apply_push_journal(remote: &dyn RemoteStorage, journal: &mut PushJournal, db: &ScuttleDb, jobs: usize) -> Result<()> {
+    // The DB to upload gets the ids of the files it describes
+    let push_db = ScuttleDb::new(Path::new(JOURNAL_DB_PATH))?;
+    push_db.use_remote_root(&journal.root_id)?;
+    let root_id = journal.root_id.clone();
+    let (mut uploaded, mut updated, mut removed, mut failed) = (0usize, 0usize, 0usize, 0usize);
+
+    for phase in [PushOp::Delete, PushOp::Upload, PushOp::Update] {
+        let pending: Vec<(usize, PlannedOp)> = journal.ops.iter()
+            .enumerate()
+            .filter(|(_, op)| op.op == phase && !op.done)
+            .map(|(i, op)| (i, op.clone()))
+            .collect();
+        let root = root_id.as_str();
+        let mut results = stream::iter(&pending)
+            .map(|(index, op)| async move { (*index, op, apply_push_op(remote, db, root, op).await) })
+            .buffer_unordered(jobs);
+        while let Some((index, op, result)) = results.next().await {
+            let id = match result {
+                Ok(id) => id,
+                Err(e) => {
+                    println!("Failed to {} {}: {}", op.op.as_str(), op.path, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            journal.record_done(index, id.as_deref())?;
+            let Some(id) = id else {
+                continue;
+            };
+            match op.op {
+                PushOp::Delete => {
+                    println!("Deleted remote {}", op.path);
+                    removed += 1;
+                }
+                PushOp::Upload => {
+                    println!("Uploaded {} as id {}", op.path, id);
+                    uploaded += 1;
+                }
+                PushOp::Update => {
+                    println!("Updated {} (id {})", op.path, id);
+                    updated += 1;
+                }
+            }
+            if op.op != PushOp::Delete {
+                db.set_remote_id(&op.path, &id)?;
+                push_db.set_remote_id(&op.path, &id)?;
+            }
+        }
+    }
+
+    println!("Push summary: uploaded={}, updated={}, deleted={}, failed={}, retries={}", uploaded, updated, removed, failed, remote.retries());
+    // The remote DB must not list files that are not there; interrupted uploads resume when the push continues
+    if failed > 0 {
+        return Err(anyhow::anyhow!("Push incomplete: {} operation(s) failed. Remote DB left unchanged; run `scuttle push --continue` to retry, or `scuttle push --abort` to undo the changes made so far.", failed));
+    }
+    Ok(())
+}
+
+/// Apply one planned operation. Returns the id the file has on the remote
+/// afterwards (for deletes, the id it had), or `None` if there was nothing to do.
+async fn apply_push_op(remote: &dyn RemoteStorage, db: &ScuttleDb, root_id: &str, op: &PlannedOp) -> Result<Option<String>> {
+    let remote_id = op.remote_id.as_deref();
+    if op.op == PushOp::Delete {
+        println!("Deleting remote: {}", op.path);
+        let found = delete_remote_path(remote, root_id, &op.path, remote_id).await?;
+        if !found {
+            println!("Remote file not found for deletion: {}", op.path);
+        }
+        return Ok(found.then(|| remote_id.unwrap_or_default().to_string()));
+    }
+    let local_path = snapshot_path(&op.path, op.new_hash.as_deref());
+    if !local_path.exists() {
+        // Recording this as done would let the swapped-in DB list a file the remote never got
+        return Err(anyhow::anyhow!("Local file missing for upload: {} (not in the working tree or the object store)", op.path));
+    }
+    // Modified files are updated in place so they keep their id, revisions and sharing
+    let id = match (op.op, remote_id) {
+        (PushOp::Upload, None) => {
+            println!("Uploading {} to remote...", op.path);
+            upload_to_path(remote, db, root_id, &op.path, &local_path).await?
+        }
+        _ => {
+            println!("Updating {} on remote...", op.path);
+            update_remote_path(remote, db, root_id, &op.path, remote_id, &local_path).await?
+        }
+    };
+    Ok(Some(id))
+}
+
+/// Replace the remote DB with the one the journaled push planned, then record
+/// the new sync state and drop the journal.
+async fn finish_push(remote: &dyn RemoteStorage, journal: &PushJournal) -> Result<()> {
+    let push_db_path = Path::new(JOURNAL_DB_PATH);
+    // Safe DB swap: find existing scuttle.db id first, upload the new DB, then delete old
+    swap_remote_db(remote, &journal.root_id, push_db_path).await?;
+    complete_push(journal)?;
+    println!("Push apply complete.");
+    Ok(())
+}
+
+/// Bookkeeping once the remote DB was replaced.
+fn complete_push(journal: &PushJournal) -> Result<()> {
+    let push_db_path = Path::new(JOURNAL_DB_PATH);
+    set_remote_binding(&journal.remote, &journal.root_id, &journal.folder_name, false)?;
+    record_synced_state(&journal.remote, push_db_path, &hash_db_file(push_db_path)?)?;
+    PushJournal::remove()
+}
+
+/// Load the journal of an interrupted push and connect to its remote. Also
+/// returns the hash of the remote DB as it is now, if there is one.
+async fn open_push_journal(remote_name: Option<&str>) -> Result<(PushJournal, Box<dyn RemoteStorage>, Option<String>)> {
+    let journal = PushJournal::load()?
+        .ok_or_else(|| anyhow::anyhow!("No interrupted push to resume."))?;
+    if let Some(name) = remote_name
+        && name != journal.remote
+    {
+        return Err(anyhow::anyhow!("The interrupted push was to '{}', not '{}'.", journal.remote, name));
+    }
+    let config = load_remote_config(Some(&journal.remote))?;
+    let remote = get_server_client(&config).await?;
+    let remote_db_hash = match find_remote_db(remote.as_ref(), &journal.root_id).await? {
+        Some(file_id) => {
+            let dest = download_remote_db(remote.as_ref(), &file_id).await?;
+            let hash = hash_db_file(&dest)?;
+            fs::remove_file(&dest).ok();
+            Some(hash)
+        }
+        None => None,
+    };
+    Ok((journal, remote, remote_db_hash))
+}
+
+/// `push --continue`: apply what an interrupted push did not get to, then swap the DB.
+pub async fn process_push_continue(remote_name: Option<&str>, jobs: Option<usize>) -> Result<()> {
+    let (mut journal, remote, remote_db_hash) = open_push_journal(remote_name).await?;
+    let remote = remote.as_ref();
+    let pending = journal.ops.iter().filter(|op| !op.done).count();
+    println!("Continuing push to {}: {} of {} operation(s) left", journal.remote, pending, journal.ops.len());
+
+    if remote_db_hash.as_deref() == Some(hash_db_file(Path::new(JOURNAL_DB_PATH))?.as_str()) {
+        println!("The remote DB was already replaced before the push was interrupted.");
+        complete_push(&journal)?;
+        println!("Push apply complete.");
+        return Ok(());
+    }
+    if remote_db_hash.unwrap_or_default() != journal.remote_db_hash {
+        return Err(anyhow::anyhow!("The remote changed since the interrupted push started. Run `scuttle push --abort`, then pull and push again."));
+    }
+
+    let config = load_remote_config(Some(&journal.remote))?;
+    let jobs = transfer_jobs(jobs, &config);
+    let db = ScuttleDb::new(Path::new(".scuttle/scuttle.db"))?;
+    db.use_remote_root(&journal.root_id)?;
+    apply_push_journal(remote, &mut journal, &db, jobs).await?;
+    finish_push(remote, &journal).await
+}
+
+/// `push --abort`: undo the changes an interrupted push made to the remote, so it
+/// matches its `scuttle.db` again. Uploaded files are deleted; updated and deleted
+/// files are restored from the local object store where it has their old content.
+pub async fn process_push_abort(remote_name: Option<&str>) -> Result<()> {
+    let (mut journal, remote, remote_db_hash) = open_push_journal(remote_name).await?;
+    let remote = remote.as_ref();
+    if remote_db_hash.as_deref() == Some(hash_db_file(Path::new(JOURNAL_DB_PATH))?.as_str()) {
+        return Err(anyhow::anyhow!("The interrupted push already replaced the remote DB, so there is nothing to undo. Run `scuttle push --continue` to finish it."));
+    }
+
+    let db = ScuttleDb::new(Path::new(".scuttle/scuttle.db"))?;
+    let root_id = journal.root_id.clone();
+    let (mut undone, mut failed) = (0usize, 0usize);
+    let mut lost = Vec::new();
+    let done: Vec<(usize, PlannedOp)> = journal.ops.iter()
+        .enumerate()
+        .filter(|(_, op)| op.done)
+        .map(|(i, op)| (i, op.clone()))
+        .rev()
+        .collect();
+    for (index, op) in done {
+        let old_content = op.old_hash.as_deref().map(object_path).filter(|p| p.exists());
+        let result = match (op.op, op.remote_id.as_deref(), &old_content) {
+            (PushOp::Upload, Some(id), _) => {
+                println!("Removing uploaded {}", op.path);
+                match remote.delete(id).await {
+                    Err(e) if is_not_found(&e) => Ok(()),
+                    other => other,
+                }
+            }
+            (PushOp::Upload, None, _) => Ok(()),
+            (PushOp::Update, Some(id), Some(old)) => {
+                println!("Restoring previous content of {}", op.path);
+                remote.update(id, old).await
+            }
+            (PushOp::Delete, _, Some(old)) => {
+                println!("Restoring deleted {}", op.path);
+                upload_to_path(remote, &db, &root_id, &op.path, old).await.map(|_| ())
+            }
+            _ => {
+                // Stays recorded as done, so `push --continue` can still finish the push
+                lost.push(op.path.clone());
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => {
+                journal.record_undone(index)?;
+                undone += 1;
+            }
+            Err(e) => {
+                println!("Failed to undo the {} of {}: {}", op.op.as_str(), op.path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    for path in &lost {
+        println!("Cannot restore the previous remote version of {}: its content is not in the local object store", path);
+    }
+    println!("Abort summary: undone={}, not restorable={}, failed={}, retries={}", undone, lost.len(), failed, remote.retries());
+    if failed > 0 {
+        return Err(anyhow::anyhow!("Abort incomplete: {} operation(s) could not be undone; run `scuttle push --abort` again to retry.", failed));
+    }
+    if !lost.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Abort incomplete: the previous remote version of {} cannot be restored. The push journal is kept; run `scuttle push --continue` to finish the push instead.",
+            lost.join(", ")
+        ));
+    }
+    PushJournal::remove()?;
+    println!("Push aborted; the remote is back at its last pushed state.");
+    Ok(())
+}
+
+/// Copy the local DB to `.scuttle/push_scuttle.db.tmp` without the rows of paths
+/// `rules` exclude, and return that path. This copy is what a push uploads.
+fn write_push_db(local_db_path: &Path, rules: &IgnoreRules) -> Result<PathBuf> {
+    let push_db_path = PathBuf::from(".scuttle/push_scuttle.db.tmp");
+    fs::copy(local_db_path, &push_db_path).context("Failed to copy local DB for push")?;
+    let db = ScuttleDb::new(&push_db_path)?;
+    for file in db.get_tracked_files()? {
+        if rules.is_ignored(Path::new(&file.path), false)? {
+            db.remove_file(&file.path)?;
+        }
+    }
+    Ok(push_db_path)
+}
+
+/// Ask on stdin how to resolve one conflicting path.
+fn prompt_conflict_resolution(path: &str) -> Result<ConflictStrategy> {
+    loop {
+        println!("Conflict in {}: keep [l]ocal, keep [r]emote or keep [b]oth? ", path);
+        io::stdout().flush().unwrap();
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).context("Failed to read resolution input")? == 0 {
+            return Err(anyhow::anyhow!("No resolution given for {}", path));
+        }
+        match input.trim().to_lowercase().as_str() {
+            "l" | "local" => return Ok(ConflictStrategy::KeepLocal),
+            "r" | "remote" => return Ok(ConflictStrategy::KeepRemote),
+            "b" | "both" => return Ok(ConflictStrategy::KeepBoth),
+            _ => println!("Please answer l, r or b."),
+        }
+    }
+}
+
+/// Where `keep-both` writes the remote version: `dir/file (conflict from <remote> <date>).ext`.
+fn conflict_copy_path(path: &str, remote_server: &str) -> String {
+    let rel = Path::new(path);
+    let stem = rel.file_stem().and_then(|s| s.to_str()).unwrap_or(path);
+    let ext = rel.extension().and_then(|e| e.to_str()).map(|e| format!(".{}", e)).unwrap_or_default();
+    let date = chrono::Utc::now().format("%Y-%m-%d");
+    let mut suffix = String::new();
+    let mut n = 1;
+    loop {
+        let name = format!("{} (conflict from {} {}{}){}", stem, remote_server, date, suffix, ext);
+        let candidate = match rel.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join(&name),
+            _ => PathBuf::from(&name),
+        };
+        if !candidate.exists() {
+            return candidate.to_string_lossy().to_string();
+        }
+        n += 1;
+        suffix = format!(" {}", n);
+    }
+}
+
+pub async fn process_pull(remote_name: Option<&str>, strategy: Option<ConflictStrategy>, jobs: Option<usize>) -> Result<()> {
+    let config = load_remote_config(remote_name)?;
+    let jobs = transfer_jobs(jobs, &config);
+    let remote_server = config.get("remote_name")
+        .and_then(|n| n.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
+    let remote = get_server_client(&config).await?;
+    let remote = remote.as_ref();
+    println!("Starting pull from remote: {}", remote_server);
+
+    let local_db_path = PathBuf::from(".scuttle/scuttle.db");
+    if !local_db_path.exists() {
+        return Err(anyhow::anyhow!("Local scuttle DB not found at {}. Run init first.", local_db_path.display()));
+    }
+
+    let (folder_name, root_id) = find_remote_root(remote, &remote_server).await;
+    let root_id = root_id
+        .ok_or_else(|| anyhow::anyhow!("Remote repo '{}' not found on {}", folder_name, remote_server))?;
+    let file_id = find_remote_db(remote, &root_id).await?
+        .ok_or_else(|| anyhow::anyhow!("No scuttle.db found in remote repo '{}'", folder_name))?;
+    let remote_db_path = download_remote_db(remote, &file_id).await?;
+    // Hash before opening, since opening a DB may migrate it
+    let remote_db_hash = hash_db_file(&remote_db_path)?;
+
+    if !remote_moved(&remote_server, &remote_db_hash)? {
+        fs::remove_file(&remote_db_path).ok();
+        println!("Already up to date.");
+        return Ok(());
+    }
+
+    ensure_same_hash_algorithm(&local_db_path, &remote_db_path)?;
+
+    // Only remote-side changes are applied; local-only changes are kept
+    let changes = classify_sync_changes(&remote_server, &local_db_path, &remote_db_path)?;
+    let mut resolutions: Vec<(&String, ConflictStrategy)> = Vec::new();
+    if !changes.both_changed.is_empty() {
+        print_remote_changes(&changes);
+        let Some(strategy) = strategy else {
+            return Err(anyhow::anyhow!("{} path(s) changed both locally and on the remote. Pull again with --strategy keep-local, keep-remote, keep-both or interactive.", changes.both_changed.len()));
+        };
+        for path in &changes.both_changed {
+            let resolution = match strategy {
+                ConflictStrategy::Interactive => prompt_conflict_resolution(path)?,
+                other => other,
+            };
+            resolutions.push((path, resolution));
+        }
+    }
+
+    let local_files = ScuttleDb::load_tracked_files(&local_db_path)?;
+    let remote_db = ScuttleDb::new(&remote_db_path)?;
+    let algorithm = remote_db.hash_algorithm()?;
+    let remote_files = remote_db.get_tracked_files()?;
+    let remote_ids = remote_db.remote_ids(&root_id)?;
+    let remote_map: HashMap<&str, &TrackedFile> = remote_files.iter()
+        .filter(|f| f.status.as_deref() != Some("deleted"))
+        .map(|f| (f.path.as_str(), f))
+        .collect();
+    let (mut to_download, to_delete): (Vec<&String>, Vec<&String>) = changes.remote_only.iter()
+        .chain(resolutions.iter().filter(|(_, r)| *r == ConflictStrategy::KeepRemote).map(|(p, _)| *p))
+        .partition(|p| remote_map.contains_key(p.as_str()));
+    if !get_base_db_path(&remote_server).exists() {
+        // Never synced, e.g. a clone whose downloads partly failed: fetch the files the
+        // DB already records with the remote content but that never arrived
+        let local_hashes: HashMap<&str, Option<&str>> = local_files.iter()
+            .filter(|f| f.status.as_deref() != Some("deleted"))
+            .map(|f| (f.path.as_str(), f.hash.as_deref()))
+            .collect();
+        let missing: Vec<&String> = remote_files.iter()
+            .filter(|f| f.status.as_deref() != Some("deleted"))
+            .filter(|f| local_hashes.get(f.path.as_str()) == Some(&f.hash.as_deref()))
+            .filter(|f| !to_download.contains(&&f.path) && !Path::new(".").join(&f.path).exists())
+            .map(|f| &f.path)
+            .collect();
+        to_download.extend(missing);
+    }
+    println!("Remote changes - updated: {}, deleted: {}; local-only changes kept: {}", to_download.len(), to_delete.len(), changes.local_only.len());
+
+    // Never overwrite or delete local edits that were not recorded in the DB
+    let local_map: HashMap<&str, &TrackedFile> = local_files.iter().map(|f| (f.path.as_str(), f)).collect();
+    let mut dirty = Vec::new();
+    // Files an earlier, partly failed pull already brought up to date
+    let mut applied = HashSet::new();
+    for path in to_download.iter().chain(to_delete.iter()) {
+        let local_path = Path::new(".").join(path);
+        if !local_path.exists() {
+            continue;
+        }
+        let recorded = local_map.get(path.as_str()).and_then(|f| f.hash.as_deref());
+        let incoming = remote_map.get(path.as_str()).and_then(|f| f.hash.as_deref());
+        let current = hash_file(&local_path, algorithm)?;
+        if incoming == Some(current.as_str()) {
+            applied.insert(path.as_str());
+        } else if recorded != Some(current.as_str()) {
+            dirty.push(path.to_string());
+        }
+    }
+    if !dirty.is_empty() {
+        return Err(anyhow::anyhow!("Local changes would be overwritten by pull: {}", dirty.join(", ")));
+    }
+
+    let mut downloaded = 0usize;
+    let mut failed = 0usize;
+    for path in &applied {
+        println!("Already up to date: {}", path);
+        store_object(&Path::new(".").join(path), algorithm)?;
+    }
+    let (root, ids, remote_map) = (root_id.as_str(), &remote_ids, &remote_map);
+    let mut downloads = stream::iter(to_download.iter().filter(|p| !applied.contains(p.as_str())))
+        .map(|path| async move {
+            let local_path = Path::new(".").join(path);
+            println!("Downloading {}...", path);
+            let remote_id = ids.get(path.as_str()).map(String::as_str);
+            let expected = remote_map.get(path.as_str()).and_then(|f| f.hash.as_deref()).map(|h| (h, algorithm));
+            let result = download_remote_path(remote, root, path, remote_id, expected, &local_path).await;
+            (path, local_path, result)
+        })
+        .buffer_unordered(jobs);
+    while let Some((path, local_path, result)) = downloads.next().await {
+        match result {
+            Ok(()) => {
+                store_object(&local_path, algorithm)?;
+                downloaded += 1;
+            }
+            Err(e) => {
+                println!("Failed to download {}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    // keep-both: the remote version goes next to the local one under a conflict name
+    let mut conflict_copies = Vec::new();
+    for (path, _) in resolutions.iter().filter(|(_, r)| *r == ConflictStrategy::KeepBoth) {
+        let Some(remote_file) = remote_map.get(path.as_str()) else {
+            continue;
+        };
+        let copy_path = conflict_copy_path(path, &remote_server);
+        println!("Downloading remote version of {} as {}...", path, copy_path);
+        let remote_id = remote_ids.get(path.as_str()).map(String::as_str);
+        let expected = remote_file.hash.as_deref().map(|h| (h, algorithm));
+        match download_remote_path(remote, &root_id, path, remote_id, expected, Path::new(&copy_path)).await {
+            Ok(()) => conflict_copies.push(copy_path),
+            Err(e) => {
+                println!("Failed to download {}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut removed = 0usize;
+    for path in &to_delete {
+        let local_path = Path::new(".").join(path);
+        if local_path.exists() {
+            fs::remove_file(&local_path).with_context(|| format!("Failed to delete {}", path))?;
+            println!("Deleted local {}", path);
+            removed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("Pull incomplete: {} file(s) failed to download. Local DB left unchanged.", failed));
+    }
+
+    // Only fast-forward if the remote history already contains ours
+    let local_head = ScuttleDb::new(&local_db_path)?.head()?;
+    let remote_head = remote_db.head()?;
+    let history_on_remote = match &local_head {
+        Some(head) => remote_db.has_commit(head)?,
+        None => true,
+    };
+    if changes.local_only.is_empty() && resolutions.is_empty() && history_on_remote {
+        // Fast-forward: the remote DB becomes the local DB
+        fs::copy(&remote_db_path, &local_db_path).context("Failed to update local DB")?;
+    } else {
+        // Merge: take the remote rows for remote-side changes and keep everything else
+        let db = ScuttleDb::new(&local_db_path)?;
+        db.use_remote_root(&root_id)?;
+        for path in changes.remote_only.iter() {
+            match remote_map.get(path.as_str()) {
+                Some(file) => db.set_committed_file(file)?,
+                None => db.remove_file(path)?,
+            }
+        }
+
+        // Stage resolutions so the next commit records them
+        for (path, resolution) in &resolutions {
+            add_file_to_db(&db, None, Path::new(path.as_str()))?;
+            println!("Resolved {} ({})", path, resolution.as_str());
+        }
+        for copy_path in &conflict_copies {
+            add_file_to_db(&db, None, Path::new(copy_path))?;
+        }
+
+        // Bring in the remote commits and record the remote head as merged
+        db.import_history(&remote_db_path)?;
+        if let Some(remote_head) = &remote_head {
+            let merged = match &local_head {
+                Some(local_head) => db.is_ancestor(remote_head, local_head)?,
+                None => false,
+            };
+            if !merged {
+                db.set_merge_head(remote_head)?;
+                if !db.staged_changes()?.is_empty() {
+                    println!("Commit the staged changes to conclude the merge.");
+                } else {
+                    let commit_id = db.commit(&format!("Merge {}", remote_server))?;
+                    println!("Created merge commit {}", &commit_id[..12]);
+                }
+            }
+        }
+    }
+    set_remote_binding(&remote_server, &root_id, &folder_name, false)?;
+    record_synced_state(&remote_server, &remote_db_path, &remote_db_hash)?;
+    fs::remove_file(&remote_db_path).ok();
+    // Everything arrived, so anything left from earlier failed downloads is stale
+    fs::remove_dir_all(PARTIAL_DOWNLOADS_DIR).ok();
+    println!("Pull complete: downloaded={}, deleted={}", downloaded, removed);
+    Ok(())
+}
+
+pub async fn process_clone(remote_folder: &str, directory: Option<&Path>, remote_name: Option<&str>, jobs: Option<usize>) -> Result<()> {
+    let config = load_remote_config(remote_name)?;
+    let jobs = transfer_jobs(jobs, &config);
+    let remote_server = config.get("remote_name")
+        .and_then(|n| n.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Remote server name not found in config"))?;
+    let remote = get_server_client(&config).await?;
+    let remote = remote.as_ref();
+
+    // Accept either a folder name or a folder id
+    let (root_id, root_name) = match remote.find_folder(remote_folder).await? {
+        Some(id) => (id, remote_folder.to_string()),
+        None => match remote.stat(remote_folder).await? {
+            Some(entry) if entry.is_folder => (entry.id, entry.name),
+            _ => return Err(anyhow::anyhow!("Remote folder '{}' not found on {}", remote_folder, remote_server)),
+        },
+    };
+    println!("Cloning '{}' (id={}) from {}", root_name, root_id, remote_server);
+    let file_id = find_remote_db(remote, &root_id).await?
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not a scuttle repo: no scuttle.db found", root_name))?;
+
+    let target = directory.map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from(&root_name));
+    if target.exists() && fs::read_dir(&target)?.next().is_some() {
+        return Err(anyhow::anyhow!("Destination {} already exists and is not empty", target.display()));
+    }
+    fs::create_dir_all(target.join(".scuttle")).context("Failed to create clone directory")?;
+    // Everything below works relative to the new working copy
+    std::env::set_current_dir(&target)?;
+
+    let remote_db_path = download_remote_db(remote, &file_id).await?;
+    let remote_db_hash = hash_db_file(&remote_db_path)?;
+    let local_db_path = PathBuf::from(".scuttle/scuttle.db");
+    fs::copy(&remote_db_path, &local_db_path).context("Failed to install remote DB")?;
+
+    let db = ScuttleDb::new(&local_db_path)?;
+    let algorithm = db.hash_algorithm()?;
+    let tracked_files = db.get_tracked_files()?;
+    let remote_ids = db.remote_ids(&root_id)?;
+    let mut downloaded = 0usize;
+    let mut failed = 0usize;
+    let (root, ids) = (root_id.as_str(), &remote_ids);
+    let mut downloads = stream::iter(tracked_files.iter().filter(|f| f.status.as_deref() != Some("deleted")))
+        .map(|tf| async move {
+            println!("Downloading {}...", tf.path);
+            let remote_id = ids.get(&tf.path).map(String::as_str);
+            let expected = tf.hash.as_deref().map(|h| (h, algorithm));
+            (tf, download_remote_path(remote, root, &tf.path, remote_id, expected, Path::new(&tf.path)).await)
+        })
+        .buffer_unordered(jobs);
+    while let Some((tf, result)) = downloads.next().await {
+        match result {
+            Ok(()) => {
+                store_object(Path::new(&tf.path), algorithm)?;
+                downloaded += 1;
+            }
+            Err(e) => {
+                println!("Failed to download {}: {}", tf.path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    set_remote_binding(&remote_server, &root_id, &root_name, true)?;
+    if failed == 0 {
+        record_synced_state(&remote_server, &remote_db_path, &remote_db_hash)?;
+    }
+    fs::remove_file(&remote_db_path).ok();
+    println!("Cloned into {}: downloaded={}, failed={}", target.display(), downloaded, failed);
+    if failed > 0 {
+        // Without a synced state the next pull fetches the files that are missing
+        return Err(anyhow::anyhow!("{} file(s) failed to download; run `scuttle pull` to retry", failed));
+    }
+    fs::remove_dir_all(PARTIAL_DOWNLOADS_DIR).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(prefix: &str, path: &str) -> Result<String> {
+        repo_relative_path(Path::new(prefix), Path::new(path)).map(|p| p.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_the_prefix() {
+        assert_eq!(resolve("", "a.txt").unwrap(), "a.txt");
+        assert_eq!(resolve("sub", "a.txt").unwrap(), "sub/a.txt");
+        assert_eq!(resolve("sub", "./a.txt").unwrap(), "sub/a.txt");
+        assert_eq!(resolve("sub/deeper", "../a.txt").unwrap(), "sub/a.txt");
+        assert_eq!(resolve("sub", "../other/./b.txt").unwrap(), "other/b.txt");
+        assert_eq!(resolve("sub", "..").unwrap(), ".");
+        assert_eq!(resolve("", ".").unwrap(), ".");
+    }
+
+    #[test]
+    fn parent_dirs_above_the_root_are_rejected() {
+        assert!(resolve("", "..").is_err());
+        assert!(resolve("", "../a.txt").is_err());
+        assert!(resolve("sub", "../../a.txt").is_err());
+        assert!(resolve("sub", "x/../../../a.txt").is_err());
+    }
+
+    #[test]
+    fn absolute_paths_resolve_against_the_root() {
+        // The current directory stands in for the repo root
+        let root = std::env::current_dir().unwrap();
+        let absolute = |rel: &str| root.join(rel).to_string_lossy().into_owned();
+        assert_eq!(resolve("sub", &absolute("src/lib.rs")).unwrap(), "src/lib.rs");
+        assert_eq!(resolve("sub", &absolute("src/../Cargo.toml")).unwrap(), "Cargo.toml");
+        assert_eq!(resolve("sub", &root.to_string_lossy()).unwrap(), ".");
+        assert!(resolve("", &absolute("../elsewhere")).is_err());
+        assert!(resolve("", "/scuttle-test-not-in-any-repo/a.txt").is_err());
+    }
+
+    #[test]
+    fn every_path_argument_is_resolved() {
+        let paths = [PathBuf::from("a.txt"), PathBuf::from("../b.txt")];
+        let resolved = repo_relative_paths(Path::new("sub/x"), &paths).unwrap();
+        assert_eq!(resolved, [PathBuf::from("sub/x/a.txt"), PathBuf::from("sub/b.txt")]);
+        assert!(repo_relative_paths(Path::new(""), &paths).is_err());
+    }
+
+    #[tokio::test]
+    async fn stale_remote_ids_fall_back_to_the_path() {
+        let remote = crate::remote::memory::MemoryRemote::new();
+        let root = remote.mkdir("proj", None).await.unwrap();
+        let dir = remote.ensure_path(&root, "docs").await.unwrap();
+        let local = std::env::temp_dir().join(format!("scuttle-stale-id-{}", std::process::id()));
+        fs::write(&local, "content").unwrap();
+        remote.upload(&local, "a.txt", Some(&dir)).await.unwrap();
+        fs::remove_file(&local).unwrap();
+
+        download_remote_path(&remote, &root, "docs/a.txt", Some("mem-999"), None, &local).await.unwrap();
+        assert_eq!(fs::read_to_string(&local).unwrap(), "content");
+        assert!(download_remote_path(&remote, &root, "docs/b.txt", None, None, &local).await.is_err());
+        fs::remove_file(&local).ok();
+    }
+}
@@ -10,9 +10,22 @@ use scuttle::process_download;
 use scuttle::process_init;
 use scuttle::process_setup;
 use scuttle::process_status;
+use scuttle::StatusFormat;
 use scuttle::process_add;
 use scuttle::process_commit;
 use scuttle::process_push;
+use scuttle::process_push_continue;
+use scuttle::process_push_abort;
+use scuttle::process_pull;
+use scuttle::process_clone;
+use scuttle::process_log;
+use scuttle::process_checkout;
+use scuttle::process_restore;
+use scuttle::process_diff;
+use scuttle::process_check_ignore;
+use scuttle::ConflictStrategy;
+use scuttle::{enter_repo_root, repo_relative_path, repo_relative_paths};
+use anyhow::Context;
 
 #[tokio::main]
 async fn main() {
@@ -23,6 +36,9 @@ async fn main() {
 
 async fn run_app() -> anyhow::Result<()> {
     let args = Cli::parse();
+    if let Some(dir) = &args.directory {
+        std::env::set_current_dir(dir).with_context(|| format!("Cannot change to {}", dir.display()))?;
+    }
 
     match args.command {
         // Here, we handle the `upload` command.
@@ -33,24 +49,75 @@ async fn run_app() -> anyhow::Result<()> {
         Commands::Download { remote_path, remote_name } => {
             process_download(&remote_path, remote_name.as_deref()).await?;
         }
-        Commands::Init { } => {
-            process_init().await?;
+        Commands::Init { hash_algorithm } => {
+            process_init(hash_algorithm.as_deref()).await?;
         }
         Commands::Setup { } => {
             process_setup().await?;
         }
-        Commands::Push { remote_name } => {
-            process_push(remote_name.as_deref()).await?;
+        Commands::Push { remote_name, force, jobs, resume, abort } => {
+            enter_repo_root()?;
+            if resume {
+                process_push_continue(remote_name.as_deref(), jobs).await?;
+            } else if abort {
+                process_push_abort(remote_name.as_deref()).await?;
+            } else {
+                process_push(remote_name.as_deref(), force, jobs).await?;
+            }
+        }
+        Commands::Pull { remote_name, strategy, jobs } => {
+            let strategy = match strategy.as_deref() {
+                Some(name) => Some(ConflictStrategy::from_name(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown strategy: {}", name))?),
+                None => None,
+            };
+            enter_repo_root()?;
+            process_pull(remote_name.as_deref(), strategy, jobs).await?;
         }
-        Commands::Status { } => {
-            process_status().await?;
+        Commands::Clone { remote_folder, directory, remote_name, jobs } => {
+            process_clone(&remote_folder, directory.as_deref(), remote_name.as_deref(), jobs).await?;
         }
-        Commands::Add { paths } => {
-            process_add(&paths).await?;
+        Commands::Status { short, porcelain, unchanged, refresh } => {
+            let format = if porcelain {
+                StatusFormat::Porcelain
+            } else if short {
+                StatusFormat::Short
+            } else {
+                StatusFormat::Long
+            };
+            enter_repo_root()?;
+            process_status(format, unchanged, refresh).await?;
+        }
+        Commands::Add { paths, refresh } => {
+            let prefix = enter_repo_root()?;
+            process_add(&repo_relative_paths(&prefix, &paths)?, refresh).await?;
         }
         Commands::Commit { message } => {
+            enter_repo_root()?;
             process_commit(&message).await?;
         }
+        Commands::Log { path, since, until, max_count, stat } => {
+            let prefix = enter_repo_root()?;
+            let path = path.map(|p| repo_relative_path(&prefix, p.as_ref())).transpose()?
+                .map(|p| p.to_string_lossy().to_string());
+            process_log(path.as_deref(), since.as_deref(), until.as_deref(), max_count, stat).await?;
+        }
+        Commands::Checkout { commit, paths, force } => {
+            let prefix = enter_repo_root()?;
+            process_checkout(&commit, &repo_relative_paths(&prefix, &paths)?, force).await?;
+        }
+        Commands::Restore { paths, committed, force } => {
+            let prefix = enter_repo_root()?;
+            process_restore(&repo_relative_paths(&prefix, &paths)?, committed, force).await?;
+        }
+        Commands::Diff { paths, staged, commit, remote, remote_name } => {
+            let prefix = enter_repo_root()?;
+            process_diff(&repo_relative_paths(&prefix, &paths)?, staged, commit.as_deref(), remote, remote_name.as_deref()).await?;
+        }
+        Commands::CheckIgnore { paths, verbose, non_matching, remote_name } => {
+            let prefix = enter_repo_root()?;
+            process_check_ignore(&repo_relative_paths(&prefix, &paths)?, verbose, non_matching, remote_name.as_deref()).await?;
+        }
     }
 
     Ok(())
@@ -60,6 +127,9 @@ async fn run_app() -> anyhow::Result<()> {
 #[derive(Parser)]
 #[clap(author, version, about)]
 struct Cli {
+    /// Run as if scuttle was started in this directory.
+    #[clap(short = 'C', value_name = "dir")]
+    directory: Option<PathBuf>,
     #[clap(subcommand)]
     command: Commands,
 }
@@ -80,6 +150,43 @@ enum Commands {
         /// Optional remote name to specify which remote to use.
         #[clap(long, value_name = "remote-name")]
         remote_name: Option<String>,
+        /// Push even if the remote changed since the last sync, overwriting those changes.
+        #[clap(long)]
+        force: bool,
+        /// How many files to transfer at once (defaults to the remote's `jobs` setting, or 4).
+        #[clap(short = 'j', long)]
+        jobs: Option<usize>,
+        /// Finish a push that was interrupted, applying what it did not get to.
+        #[clap(long = "continue", conflicts_with_all = ["force", "abort"])]
+        resume: bool,
+        /// Undo the remote changes of a push that was interrupted.
+        #[clap(long, conflicts_with_all = ["force", "jobs"])]
+        abort: bool,
+    },
+    /// Pull remote repository changes into the working tree.
+    Pull {
+        /// Optional remote name to specify which remote to use.
+        #[clap(long, value_name = "remote-name")]
+        remote_name: Option<String>,
+        /// How to resolve paths changed on both sides.
+        #[clap(long, value_parser = ["keep-local", "keep-remote", "keep-both", "interactive"])]
+        strategy: Option<String>,
+        /// How many files to transfer at once (defaults to the remote's `jobs` setting, or 4).
+        #[clap(short = 'j', long)]
+        jobs: Option<usize>,
+    },
+    /// Creates a working copy from an existing remote repo.
+    Clone {
+        /// Name or folder ID of the remote repo root.
+        remote_folder: String,
+        /// Directory to clone into (defaults to the remote folder name).
+        directory: Option<PathBuf>,
+        /// Optional remote name to specify which remote to use.
+        #[clap(long, value_name = "remote-name")]
+        remote_name: Option<String>,
+        /// How many files to transfer at once (defaults to the remote's `jobs` setting, or 4).
+        #[clap(short = 'j', long)]
+        jobs: Option<usize>,
     },
     /// Downloads a file from the configured cloud service.
     Download {
@@ -91,16 +198,34 @@ enum Commands {
     },
     /// Initializes the configuration for the cloud service.
     Init {
+        /// Hash algorithm for file contents; fixed once files are tracked.
+        #[arg(long, value_parser = ["sha256", "blake3"])]
+        hash_algorithm: Option<String>,
     },
     Setup {
     },
     /// Shows the status of local files compared to tracked files.
     Status {
+        /// Print one `XY path` line per file, like git's short format.
+        #[clap(short = 's', long)]
+        short: bool,
+        /// Stable machine-readable output (same line format as --short).
+        #[clap(long)]
+        porcelain: bool,
+        /// Also list tracked files without changes.
+        #[clap(long)]
+        unchanged: bool,
+        /// Re-hash every file instead of trusting unchanged size, mtime, inode and ctime.
+        #[clap(long)]
+        refresh: bool,
     },
     /// Adds files to the staging area.
     Add {
         /// Paths of files to add.
         paths: Vec<PathBuf>,
+        /// Re-hash every file instead of trusting unchanged size, mtime, inode and ctime.
+        #[clap(long)]
+        refresh: bool,
     },
     /// Commits the current state with a message.
     Commit {
@@ -108,4 +233,76 @@ enum Commands {
         #[clap(short = 'm', long = "message")]
         message: String,
     },
+    /// Shows the commit history, newest first.
+    Log {
+        /// Only show commits that touched this file.
+        #[clap(long)]
+        path: Option<String>,
+        /// Only show commits made at or after this date (YYYY-MM-DD, 'YYYY-MM-DD HH:MM' or RFC 3339).
+        #[clap(long)]
+        since: Option<String>,
+        /// Only show commits made at or before this date.
+        #[clap(long)]
+        until: Option<String>,
+        /// Show at most this many commits.
+        #[clap(short = 'n', long = "max-count")]
+        max_count: Option<usize>,
+        /// List the paths each commit added, modified or deleted.
+        #[clap(long)]
+        stat: bool,
+    },
+    /// Rewrites the working tree, or only the given paths, to a past commit and stages the result.
+    Checkout {
+        /// Commit id (or a unique prefix of it), or HEAD.
+        commit: String,
+        /// Files or directories to check out (defaults to the whole tree).
+        paths: Vec<PathBuf>,
+        /// Discard uncommitted changes to the affected files.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Shows changes between the working tree, staged state, commits and the remote.
+    Diff {
+        /// Only show changes to these files or directories.
+        paths: Vec<PathBuf>,
+        /// Compare the staged state instead of the working tree (against the last commit by default).
+        #[clap(long, alias = "cached")]
+        staged: bool,
+        /// Compare against this commit (id prefix or HEAD) instead.
+        #[clap(long, conflicts_with = "remote")]
+        commit: Option<String>,
+        /// Compare the local DB against the remote one, as the next push would apply it.
+        #[clap(long, conflicts_with = "staged")]
+        remote: bool,
+        /// Optional remote name to specify which remote to use.
+        #[clap(long, value_name = "remote-name", requires = "remote")]
+        remote_name: Option<String>,
+    },
+    /// Restores files in the working tree to their staged content.
+    Restore {
+        /// Files or directories to restore.
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+        /// Restore the content of the last commit instead, unstaging the files.
+        #[clap(long)]
+        committed: bool,
+        /// Discard local changes to the restored files.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Shows which of the given paths are excluded by .scuttleignore rules.
+    CheckIgnore {
+        /// Paths to check.
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+        /// Show the ignore file, line and pattern that decided each path.
+        #[clap(short = 'v', long)]
+        verbose: bool,
+        /// With --verbose, also list paths that no rule matched.
+        #[clap(short = 'n', long, requires = "verbose")]
+        non_matching: bool,
+        /// Also apply this remote's exclude list, as push does.
+        #[clap(long, value_name = "remote-name")]
+        remote_name: Option<String>,
+    },
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::remote::{RemoteEntry, RemoteError, RemoteStorage};
+
+/// `RemoteStorage` that mirrors repos into a directory on disk (NAS mount, USB drive, ...).
+///
+/// Ids are `/`-separated paths relative to `base`, so the remote layout is the
+/// same `<repo>/.scuttle/scuttle.db` tree that push creates on Drive.
+pub struct LocalRemote {
+    base: PathBuf,
+}
+
+impl LocalRemote {
+    pub fn new(base: &Path) -> Result<Self> {
+        if !base.is_dir() {
+            return Err(anyhow::anyhow!("Local remote directory not found: {}", base.display()));
+        }
+        Ok(LocalRemote { base: base.to_path_buf() })
+    }
+
+    /// Map an id to a path under `base`, refusing anything that escapes it.
+    fn resolve(&self, id: &str) -> Result<PathBuf> {
+        let rel = Path::new(id);
+        if rel.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(anyhow::anyhow!("Invalid local remote id: {}", id));
+        }
+        Ok(self.base.join(rel))
+    }
+
+    /// Like `resolve`, but the entry must exist.
+    fn resolve_existing(&self, id: &str) -> Result<PathBuf> {
+        let path = self.resolve(id)?;
+        if !path.exists() {
+            return Err(RemoteError::NotFound(id.to_string()).into());
+        }
+        Ok(path)
+    }
+
+    fn child_id(parent_id: Option<&str>, name: &str) -> String {
+        match parent_id {
+            Some(parent) if !parent.is_empty() => format!("{}/{}", parent.trim_end_matches('/'), name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn entry(id: String, path: &Path) -> Result<RemoteEntry> {
+        let metadata = fs::metadata(path)?;
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+        Ok(RemoteEntry {
+            id,
+            name,
+            is_folder: metadata.is_dir(),
+            size: metadata.is_file().then_some(metadata.len()),
+        })
+    }
+
+    /// Copy file contents only; permissions stay those of a newly created file,
+    /// so read-only snapshots do not make remote or working copies read-only.
+    fn copy_contents(from: &Path, to: &Path) -> Result<()> {
+        let mut source = fs::File::open(from)?;
+        let mut dest = fs::File::create(to)?;
+        io::copy(&mut source, &mut dest)?;
+        Ok(())
+    }
+
+    /// Copy next to the target first so a failed copy never leaves a truncated file behind.
+    fn copy_into_place(local_path: &Path, target: &Path) -> Result<()> {
+        let name = target.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let tmp = target.with_file_name(format!(".{}.scuttle-tmp", name));
+        Self::copy_contents(local_path, &tmp).with_context(|| format!("Failed to copy {} to local remote", local_path.display()))?;
+        fs::rename(&tmp, target).context("Failed to move uploaded file into place")?;
+        Ok(())
+    }
+
+    fn find_file_under(&self, dir_id: &str, name: &str) -> Result<Option<String>> {
+        for entry in fs::read_dir(self.resolve(dir_id)?)? {
+            let entry = entry?;
+            let entry_name = entry.file_name().to_string_lossy().to_string();
+            let id = Self::child_id(Some(dir_id), &entry_name);
+            if entry.file_type()?.is_dir() {
+                if let Some(found) = self.find_file_under(&id, name)? {
+                    return Ok(Some(found));
+                }
+            } else if entry_name == name {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl RemoteStorage for LocalRemote {
+    async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(self.resolve(folder_id)?).context("Failed to list local remote folder")? {
+            let entry = entry?;
+            let id = Self::child_id(Some(folder_id), &entry.file_name().to_string_lossy());
+            entries.push(Self::entry(id, &entry.path())?);
+        }
+        Ok(entries)
+    }
+
+    async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>> {
+        let path = self.resolve(id)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self::entry(id.to_string(), &path)?))
+    }
+
+    async fn find_folder(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.resolve(name)?.is_dir().then(|| name.to_string()))
+    }
+
+    async fn find_file(&self, name: &str) -> Result<Option<String>> {
+        self.find_file_under("", name)
+    }
+
+    async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>> {
+        let id = Self::child_id(Some(parent_id), name);
+        Ok(self.resolve(&id)?.exists().then_some(id))
+    }
+
+    async fn upload(&self, local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+        if let Some(parent) = parent_id {
+            self.resolve_existing(parent)?;
+        }
+        let id = Self::child_id(parent_id, name);
+        Self::copy_into_place(local_path, &self.resolve(&id)?)?;
+        Ok(id)
+    }
+
+    async fn update(&self, id: &str, local_path: &Path) -> Result<()> {
+        let target = self.resolve_existing(id)?;
+        if target.is_dir() {
+            return Err(anyhow::anyhow!("Cannot update folder {} with file content", id));
+        }
+        Self::copy_into_place(local_path, &target)
+    }
+
+    async fn download(&self, id: &str, destination: &Path) -> Result<()> {
+        Self::copy_contents(&self.resolve_existing(id)?, destination)
+            .with_context(|| format!("Failed to copy {} from local remote", id))
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let path = self.resolve_existing(id)?;
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    async fn mkdir(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let id = Self::child_id(parent_id, name);
+        fs::create_dir_all(self.resolve(&id)?).context("Failed to create local remote folder")?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use crate::remote::{RemoteEntry, RemoteError, RemoteStorage};
+
+/// `RemoteStorage` that keeps everything in memory, for tests.
+///
+/// Ids are opaque (`mem-1`, `mem-2`, ...) and names need not be unique within a
+/// folder, as on Drive, so code that treats ids as paths fails here even though
+/// it passes against `LocalRemote`.
+#[derive(Default)]
+pub struct MemoryRemote {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// In creation order, so lookups by name find the oldest match first.
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    id: String,
+    name: String,
+    parent_id: Option<String>,
+    /// `None` for folders.
+    content: Option<Vec<u8>>,
+}
+
+impl Node {
+    fn entry(&self) -> RemoteEntry {
+        RemoteEntry {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            is_folder: self.content.is_none(),
+            size: self.content.as_ref().map(|c| c.len() as u64),
+        }
+    }
+}
+
+impl State {
+    fn get(&self, id: &str) -> Option<&Node> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut Node> {
+        self.nodes.iter_mut().find(|n| n.id == id).ok_or_else(|| RemoteError::NotFound(id.to_string()).into())
+    }
+
+    fn create(&mut self, name: &str, parent_id: Option<&str>, content: Option<Vec<u8>>) -> Result<String> {
+        if let Some(parent) = parent_id
+            && self.get(parent).is_none_or(|n| n.content.is_some())
+        {
+            return Err(RemoteError::NotFound(parent.to_string()).into());
+        }
+        self.next_id += 1;
+        let id = format!("mem-{}", self.next_id);
+        self.nodes.push(Node { id: id.clone(), name: name.to_string(), parent_id: parent_id.map(str::to_string), content });
+        Ok(id)
+    }
+
+    fn find(&self, matches: impl Fn(&Node) -> bool) -> Option<String> {
+        self.nodes.iter().find(|n| matches(n)).map(|n| n.id.clone())
+    }
+}
+
+impl MemoryRemote {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl RemoteStorage for MemoryRemote {
+    async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+        let state = self.state();
+        if state.get(folder_id).is_none_or(|n| n.content.is_some()) {
+            return Err(RemoteError::NotFound(folder_id.to_string()).into());
+        }
+        Ok(state.nodes.iter().filter(|n| n.parent_id.as_deref() == Some(folder_id)).map(Node::entry).collect())
+    }
+
+    async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>> {
+        Ok(self.state().get(id).map(Node::entry))
+    }
+
+    async fn find_folder(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.state().find(|n| n.name == name && n.content.is_none()))
+    }
+
+    async fn find_file(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.state().find(|n| n.name == name && n.content.is_some()))
+    }
+
+    async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>> {
+        Ok(self.state().find(|n| n.name == name && n.parent_id.as_deref() == Some(parent_id)))
+    }
+
+    async fn upload(&self, local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let content = fs::read(local_path).with_context(|| format!("Failed to read {}", local_path.display()))?;
+        self.state().create(name, parent_id, Some(content))
+    }
+
+    async fn update(&self, id: &str, local_path: &Path) -> Result<()> {
+        let content = fs::read(local_path).with_context(|| format!("Failed to read {}", local_path.display()))?;
+        let mut state = self.state();
+        let node = state.get_mut(id)?;
+        if node.content.is_none() {
+            return Err(anyhow::anyhow!("Cannot update folder {} with file content", id));
+        }
+        node.content = Some(content);
+        Ok(())
+    }
+
+    async fn download(&self, id: &str, destination: &Path) -> Result<()> {
+        let content = match self.state().get(id) {
+            Some(Node { content: Some(content), .. }) => content.clone(),
+            Some(_) => return Err(anyhow::anyhow!("Cannot download folder {}", id)),
+            None => return Err(RemoteError::NotFound(id.to_string()).into()),
+        };
+        fs::write(destination, content).with_context(|| format!("Failed to write {}", destination.display()))
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let mut state = self.state();
+        state.get_mut(id)?;
+        // Folders go with everything in them
+        let mut doomed = vec![id.to_string()];
+        let mut index = 0;
+        while let Some(parent) = doomed.get(index).cloned() {
+            doomed.extend(state.nodes.iter().filter(|n| n.parent_id.as_deref() == Some(parent.as_str())).map(|n| n.id.clone()));
+            index += 1;
+        }
+        state.nodes.retain(|n| !doomed.contains(&n.id));
+        Ok(())
+    }
+
+    async fn mkdir(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        self.state().create(name, parent_id, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::is_not_found;
+
+    fn temp_file(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("scuttle-memory-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn paths_resolve_through_opaque_ids() {
+        let remote = MemoryRemote::new();
+        let root = remote.mkdir("proj", None).await.unwrap();
+        let dir = remote.ensure_path(&root, "a/b").await.unwrap();
+        assert_eq!(remote.ensure_path(&root, "a/b").await.unwrap(), dir);
+        let local = temp_file("paths", "content");
+        let id = remote.upload(&local, "f.txt", Some(&dir)).await.unwrap();
+
+        assert_eq!(remote.find_by_path(&root, "a/b/f.txt").await.unwrap(), Some(id.clone()));
+        assert_eq!(remote.find_by_path(&root, "a/f.txt").await.unwrap(), None);
+        assert_eq!(remote.find_folder("proj").await.unwrap(), Some(root.clone()));
+        let entry = remote.stat(&id).await.unwrap().unwrap();
+        assert!(!entry.is_folder && entry.name == "f.txt" && entry.size == Some(7));
+        fs::remove_file(local).ok();
+    }
+
+    #[tokio::test]
+    async fn update_keeps_the_id_and_delete_takes_the_contents() {
+        let remote = MemoryRemote::new();
+        let root = remote.mkdir("proj", None).await.unwrap();
+        let dir = remote.mkdir("sub", Some(&root)).await.unwrap();
+        let (first, second) = (temp_file("first", "one"), temp_file("second", "two"));
+        let id = remote.upload(&first, "f.txt", Some(&dir)).await.unwrap();
+        remote.update(&id, &second).await.unwrap();
+        remote.download(&id, &first).await.unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "two");
+
+        remote.delete(&dir).await.unwrap();
+        assert!(remote.stat(&id).await.unwrap().is_none());
+        assert!(is_not_found(&remote.download(&id, &first).await.unwrap_err()));
+        assert!(is_not_found(&remote.upload(&second, "g.txt", Some(&dir)).await.unwrap_err()));
+        assert_eq!(remote.list(&root).await.unwrap().len(), 0);
+        fs::remove_file(first).ok();
+        fs::remove_file(second).ok();
+    }
+}
@@ -0,0 +1,121 @@
+pub mod local;
+pub mod memory;
+pub mod retry;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Errors backends report in a form the sync logic can act on.
+#[derive(Debug)]
+pub enum RemoteError {
+    /// The entry (or its parent) does not exist, e.g. a stale recorded id.
+    NotFound(String),
+    /// The service asked us to slow down, possibly saying for how long.
+    RateLimited { message: String, retry_after: Option<Duration> },
+    /// A failure that may go away on its own: a server error or a dropped connection.
+    Transient(String),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::NotFound(what) => write!(f, "Not found on remote: {}", what),
+            RemoteError::RateLimited { message, .. } => write!(f, "Rate limited: {}", message),
+            RemoteError::Transient(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// Whether an error from a remote call means the addressed entry does not exist.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<RemoteError>(), Some(RemoteError::NotFound(_)))
+}
+
+/// A file or folder as seen by a remote backend.
+#[derive(Debug, Clone)]
+pub struct RemoteEntry {
+    pub id: String,
+    pub name: String,
+    pub is_folder: bool,
+    pub size: Option<u64>,
+}
+
+/// Storage operations every remote backend must provide.
+///
+/// Entries are addressed by backend-specific ids (Drive file ids, relative
+/// paths, ...). Push, pull and the other commands only talk to remotes through
+/// this trait, so adding a backend does not touch the sync logic.
+#[async_trait]
+pub trait RemoteStorage: Send + Sync {
+    /// List the direct children of a folder.
+    async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>>;
+
+    /// Fetch metadata for a single entry. Returns `None` if it does not exist.
+    async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>>;
+
+    /// Search the whole remote for a folder by name. Used to locate repo roots.
+    async fn find_folder(&self, name: &str) -> Result<Option<String>>;
+
+    /// Search the whole remote for a file by name.
+    async fn find_file(&self, name: &str) -> Result<Option<String>>;
+
+    /// Find an entry by name directly under `parent_id`.
+    async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>>;
+
+    /// Upload `local_path` as `name` under an optional parent. Returns the new id.
+    async fn upload(&self, local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String>;
+
+    /// Replace the content of an existing file in place, keeping its id.
+    async fn update(&self, id: &str, local_path: &Path) -> Result<()>;
+
+    /// Download an entry into `destination`. Backends that can fetch byte ranges
+    /// treat an existing `destination` as an interrupted download of the same
+    /// entry and fetch only the rest; others overwrite it.
+    async fn download(&self, id: &str, destination: &Path) -> Result<()>;
+
+    /// Delete an entry by id.
+    async fn delete(&self, id: &str) -> Result<()>;
+
+    /// Create a folder under an optional parent. Returns the folder id.
+    async fn mkdir(&self, name: &str, parent_id: Option<&str>) -> Result<String>;
+
+    /// How many failed calls were retried so far. Only `retry::RetryingRemote` retries.
+    fn retries(&self) -> usize {
+        0
+    }
+
+    /// Resolve a `/`-separated path under `root_id`, one component at a time.
+    async fn find_by_path(&self, root_id: &str, rel_path: &str) -> Result<Option<String>> {
+        let path = rel_path.replace('\\', "/");
+        let mut current = root_id.to_string();
+        for comp in path.split('/').filter(|c| !c.is_empty()) {
+            match self.find_in_folder(comp, &current).await? {
+                Some(id) => current = id,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+
+    /// Ensure a `/`-separated folder path exists under `root_id`, creating
+    /// missing folders. Returns the id of the deepest folder.
+    ///
+    /// Transfers call this concurrently, possibly for the same folders; backends
+    /// whose folder creation is not idempotent must serialize it.
+    async fn ensure_path(&self, root_id: &str, rel_dir: &str) -> Result<String> {
+        let path = rel_dir.replace('\\', "/");
+        let mut parent = root_id.to_string();
+        for comp in path.split('/').map(str::trim).filter(|c| !c.is_empty()) {
+            parent = match self.find_in_folder(comp, &parent).await? {
+                Some(id) => id,
+                None => self.mkdir(comp, Some(&parent)).await?,
+            };
+        }
+        Ok(parent)
+    }
+}
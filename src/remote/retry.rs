@@ -0,0 +1,447 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use super::{RemoteEntry, RemoteError, RemoteStorage};
+
+/// How often, and how patiently, failed remote calls are tried again.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry; each further retry doubles it.
+    pub base_delay: Duration,
+    /// Upper bound for the doubled delay. A longer `Retry-After` still wins.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(32),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before retry number `retry` (counting from 1): exponential backoff
+    /// with jitter, so concurrent transfers do not retry in lockstep, but never
+    /// shorter than what the service asked for.
+    fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1 << (retry - 1).min(16)).min(self.max_delay);
+        let jittered = backoff.mul_f64(0.5 + jitter() / 2.0);
+        retry_after.map_or(jittered, |after| after.max(jittered))
+    }
+}
+
+/// A random factor in `[0, 1)`. Each `RandomState` is seeded differently, which
+/// is all the randomness jitter needs.
+fn jitter() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let bits = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether `err` is worth retrying and, if so, how long the service asked us to wait.
+fn retryable(err: &anyhow::Error) -> Option<Option<Duration>> {
+    match err.downcast_ref::<RemoteError>()? {
+        RemoteError::RateLimited { retry_after, .. } => Some(*retry_after),
+        RemoteError::Transient(_) => Some(None),
+        RemoteError::NotFound(_) => None,
+    }
+}
+
+/// Wraps a backend so every call follows one `RetryPolicy`, and counts the
+/// retries so commands can report them.
+///
+/// Backends only classify their failures as `RemoteError::RateLimited` or
+/// `RemoteError::Transient`; anything else fails on the first attempt. Uploads
+/// and folder creation are not idempotent, so they are only re-issued once a
+/// lookup shows the failed attempt did not create the entry after all.
+pub struct RetryingRemote {
+    inner: Box<dyn RemoteStorage>,
+    policy: RetryPolicy,
+    retries: AtomicUsize,
+}
+
+impl RetryingRemote {
+    pub fn new(inner: Box<dyn RemoteStorage>, policy: RetryPolicy) -> Self {
+        RetryingRemote { inner, policy, retries: AtomicUsize::new(0) }
+    }
+
+    /// Wait before the retry that follows failed attempt `attempt`, or hand `err`
+    /// back if it is not worth retrying or the attempts are used up.
+    async fn wait_to_retry(&self, what: &str, attempt: u32, err: anyhow::Error) -> Result<()> {
+        let Some(retry_after) = retryable(&err) else {
+            return Err(err);
+        };
+        if attempt >= self.policy.max_attempts {
+            return Err(anyhow::anyhow!("{} (gave up after {} attempts)", err, attempt));
+        }
+        let delay = self.policy.delay(attempt, retry_after);
+        println!("{} failed: {}; retrying in {:.1}s ({}/{})", what, err, delay.as_secs_f64(), attempt, self.policy.max_attempts - 1);
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+
+    async fn run<T, F, Fut>(&self, what: &str, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Ok(value) => return Ok(value),
+                Err(err) => self.wait_to_retry(what, attempt, err).await?,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Like `run`, for calls that create an entry. A transient failure may hide a
+    /// create that went through, so before issuing it again the parent is checked
+    /// for a matching entry, which is taken instead of making a duplicate. Entries
+    /// that had the same name before the first attempt never count as that match.
+    async fn run_create<F, Fut>(&self, what: &str, created: Created<'_>, call: F) -> Result<String>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let existing = self.same_name_entries(&created).await?;
+        let mut attempt = 1;
+        loop {
+            let err = match call().await {
+                Ok(id) => return Ok(id),
+                Err(err) => err,
+            };
+            let maybe_created = matches!(err.downcast_ref::<RemoteError>(), Some(RemoteError::Transient(_)));
+            self.wait_to_retry(what, attempt, err).await?;
+            if maybe_created && let Some(id) = self.find_created(&created, &existing).await? {
+                println!("{}: the earlier attempt went through", what);
+                return Ok(id);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Ids of the entries named like `created` that exist before it is made.
+    async fn same_name_entries(&self, created: &Created<'_>) -> Result<Vec<String>> {
+        let what = format!("Looking for {}", created.name);
+        let Some(parent) = created.parent_id else {
+            return Ok(self.find_unparented(created).await?.into_iter().collect());
+        };
+        // Usually the name is free, and one lookup shows that
+        if self.run(&what, || self.inner.find_in_folder(created.name, parent)).await?.is_none() {
+            return Ok(Vec::new());
+        }
+        let entries = self.run(&what, || self.inner.list(parent)).await?;
+        Ok(entries.into_iter().filter(|e| e.name == created.name).map(|e| e.id).collect())
+    }
+
+    async fn find_unparented(&self, created: &Created<'_>) -> Result<Option<String>> {
+        let what = format!("Looking for {}", created.name);
+        if created.is_folder {
+            self.run(&what, || self.inner.find_folder(created.name)).await
+        } else {
+            self.run(&what, || self.inner.find_file(created.name)).await
+        }
+    }
+
+    async fn find_created(&self, created: &Created<'_>, existing: &[String]) -> Result<Option<String>> {
+        let what = format!("Looking for {}", created.name);
+        let candidates = match created.parent_id {
+            Some(parent) => self.run(&what, || self.inner.list(parent)).await?,
+            None => match self.find_unparented(created).await? {
+                Some(id) => self.run(&what, || self.inner.stat(&id)).await?.into_iter().collect(),
+                None => Vec::new(),
+            },
+        };
+        Ok(candidates.into_iter()
+            .find(|e| {
+                e.name == created.name
+                    && !existing.contains(&e.id)
+                    && e.is_folder == created.is_folder
+                    && (created.size.is_none() || e.size == created.size)
+            })
+            .map(|e| e.id))
+    }
+}
+
+/// The entry a create call makes, as far as a lookup can recognise it.
+struct Created<'a> {
+    name: &'a str,
+    parent_id: Option<&'a str>,
+    is_folder: bool,
+    /// Size of the uploaded content, if known.
+    size: Option<u64>,
+}
+
+#[async_trait]
+impl RemoteStorage for RetryingRemote {
+    async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+        self.run("Listing a folder", || self.inner.list(folder_id)).await
+    }
+
+    async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>> {
+        self.run("Reading file metadata", || self.inner.stat(id)).await
+    }
+
+    async fn find_folder(&self, name: &str) -> Result<Option<String>> {
+        self.run("Searching for a folder", || self.inner.find_folder(name)).await
+    }
+
+    async fn find_file(&self, name: &str) -> Result<Option<String>> {
+        self.run("Searching for a file", || self.inner.find_file(name)).await
+    }
+
+    async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>> {
+        self.run("Searching a folder", || self.inner.find_in_folder(name, parent_id)).await
+    }
+
+    async fn upload(&self, local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let size = std::fs::metadata(local_path).ok().map(|m| m.len());
+        let created = Created { name, parent_id, is_folder: false, size };
+        self.run_create(&format!("Uploading {}", local_path.display()), created, || self.inner.upload(local_path, name, parent_id)).await
+    }
+
+    async fn update(&self, id: &str, local_path: &Path) -> Result<()> {
+        self.run(&format!("Updating {}", local_path.display()), || self.inner.update(id, local_path)).await
+    }
+
+    async fn download(&self, id: &str, destination: &Path) -> Result<()> {
+        self.run(&format!("Downloading {}", id), || self.inner.download(id, destination)).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.run(&format!("Deleting {}", id), || self.inner.delete(id)).await
+    }
+
+    async fn mkdir(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let created = Created { name, parent_id, is_folder: true, size: None };
+        self.run_create(&format!("Creating folder {}", name), created, || self.inner.mkdir(name, parent_id)).await
+    }
+
+    async fn find_by_path(&self, root_id: &str, rel_path: &str) -> Result<Option<String>> {
+        self.run(&format!("Looking up {}", rel_path), || self.inner.find_by_path(root_id, rel_path)).await
+    }
+
+    async fn ensure_path(&self, root_id: &str, rel_dir: &str) -> Result<String> {
+        self.run(&format!("Creating folders for {}", rel_dir), || self.inner.ensure_path(root_id, rel_dir)).await
+    }
+
+    fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A backend whose calls fail with the queued errors, in order, then succeed.
+    #[derive(Default)]
+    struct FlakyRemote {
+        failures: Mutex<Vec<RemoteError>>,
+        /// Calls that reached the backend, failed or not.
+        calls: Arc<AtomicUsize>,
+        /// Ids of the files in the backend, `<parent>/<name>#<n>`.
+        created: Arc<Mutex<Vec<String>>>,
+        /// Uploads create their entry even when the call then fails, like a
+        /// response lost after the request went through.
+        create_then_fail: bool,
+    }
+
+    impl FlakyRemote {
+        fn failing(failures: Vec<RemoteError>) -> Self {
+            FlakyRemote { failures: Mutex::new(failures), ..Default::default() }
+        }
+
+        fn call(&self) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let mut failures = self.failures.lock().unwrap();
+            if failures.is_empty() {
+                return Ok(());
+            }
+            Err(failures.remove(0).into())
+        }
+
+        fn create(&self, name: &str, parent_id: Option<&str>) -> String {
+            let mut created = self.created.lock().unwrap();
+            let id = format!("{}/{}#{}", parent_id.unwrap_or_default(), name, created.len() + 1);
+            created.push(id.clone());
+            id
+        }
+
+        fn entry(id: &str) -> RemoteEntry {
+            let name = id.rsplit('/').next().and_then(|n| n.split('#').next()).unwrap_or(id).to_string();
+            RemoteEntry { id: id.to_string(), name, is_folder: false, size: None }
+        }
+    }
+
+    #[async_trait]
+    impl RemoteStorage for FlakyRemote {
+        async fn list(&self, folder_id: &str) -> Result<Vec<RemoteEntry>> {
+            let prefix = format!("{}/", folder_id);
+            let created = self.created.lock().unwrap();
+            Ok(created.iter().filter(|id| id.starts_with(&prefix)).map(|id| Self::entry(id)).collect())
+        }
+
+        async fn stat(&self, id: &str) -> Result<Option<RemoteEntry>> {
+            Ok(Some(Self::entry(id)))
+        }
+
+        async fn find_folder(&self, _name: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        async fn find_file(&self, _name: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        /// The oldest entry with that name, as a search may well return.
+        async fn find_in_folder(&self, name: &str, parent_id: &str) -> Result<Option<String>> {
+            let entries = self.list(parent_id).await?;
+            Ok(entries.into_iter().find(|e| e.name == name).map(|e| e.id))
+        }
+
+        async fn upload(&self, _local_path: &Path, name: &str, parent_id: Option<&str>) -> Result<String> {
+            let early = self.create_then_fail.then(|| self.create(name, parent_id));
+            self.call()?;
+            Ok(early.unwrap_or_else(|| self.create(name, parent_id)))
+        }
+
+        async fn update(&self, _id: &str, _local_path: &Path) -> Result<()> {
+            self.call()
+        }
+
+        async fn download(&self, _id: &str, _destination: &Path) -> Result<()> {
+            self.call()
+        }
+
+        async fn delete(&self, _id: &str) -> Result<()> {
+            self.call()
+        }
+
+        async fn mkdir(&self, name: &str, _parent_id: Option<&str>) -> Result<String> {
+            self.call().map(|_| name.to_string())
+        }
+    }
+
+    const FAST: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(4),
+    };
+
+    fn transient() -> RemoteError {
+        RemoteError::Transient("connection reset".to_string())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        let policy = RetryPolicy { max_attempts: 10, base_delay: Duration::from_millis(100), max_delay: Duration::from_secs(1) };
+        for retry in 1..=8 {
+            let backoff = Duration::from_millis(100 * (1 << (retry - 1))).min(Duration::from_secs(1));
+            for _ in 0..50 {
+                let delay = policy.delay(retry, None);
+                assert!(delay >= backoff / 2 && delay <= backoff, "retry {}: {:?} outside {:?}", retry, delay, backoff);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Duration::from_secs(60));
+        // Beyond the cap too: the service knows best
+        assert_eq!(policy.delay(10, Some(Duration::from_secs(90))), Duration::from_secs(90));
+        // A shorter Retry-After does not undercut the backoff
+        assert!(policy.delay(3, Some(Duration::from_millis(1))) >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_call_succeeds() {
+        let flaky = FlakyRemote::failing(vec![
+            transient(),
+            RemoteError::RateLimited { message: "slow down".to_string(), retry_after: Some(Duration::from_millis(2)) },
+        ]);
+        let calls = flaky.calls.clone();
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        remote.download("id", Path::new("file")).await.unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        assert_eq!(remote.retries(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_not_retried() {
+        let flaky = FlakyRemote::failing(vec![RemoteError::NotFound("gone".to_string()), transient()]);
+        let calls = flaky.calls.clone();
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let err = remote.delete("gone").await.unwrap_err();
+        assert!(crate::remote::is_not_found(&err));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(remote.retries(), 0);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let flaky = FlakyRemote::failing((0..10).map(|_| transient()).collect());
+        let calls = flaky.calls.clone();
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let err = remote.update("id", Path::new("file")).await.unwrap_err();
+        assert!(err.to_string().contains("gave up after 3 attempts"), "{}", err);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        assert_eq!(remote.retries(), 2);
+    }
+
+    #[tokio::test]
+    async fn upload_that_went_through_is_not_repeated() {
+        let flaky = FlakyRemote { create_then_fail: true, ..FlakyRemote::failing(vec![transient()]) };
+        let (calls, created) = (flaky.calls.clone(), flaky.created.clone());
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let id = remote.upload(Path::new("missing-local-file"), "a.txt", Some("root")).await.unwrap();
+        assert_eq!(id, "root/a.txt#1");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(*created.lock().unwrap(), ["root/a.txt#1"]);
+    }
+
+    #[tokio::test]
+    async fn upload_that_did_not_go_through_is_repeated() {
+        let flaky = FlakyRemote::failing(vec![transient()]);
+        let (calls, created) = (flaky.calls.clone(), flaky.created.clone());
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let id = remote.upload(Path::new("missing-local-file"), "a.txt", Some("root")).await.unwrap();
+        assert_eq!(id, "root/a.txt#1");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(*created.lock().unwrap(), ["root/a.txt#1"]);
+    }
+
+    #[tokio::test]
+    async fn entry_that_existed_before_the_upload_is_not_taken_for_it() {
+        let flaky = FlakyRemote::failing(vec![transient()]);
+        let (calls, created) = (flaky.calls.clone(), flaky.created.clone());
+        created.lock().unwrap().push("root/scuttle.db#0".to_string());
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let id = remote.upload(Path::new("missing-local-file"), "scuttle.db", Some("root")).await.unwrap();
+        assert_eq!(id, "root/scuttle.db#2");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn upload_next_to_an_older_entry_that_went_through_is_not_repeated() {
+        let flaky = FlakyRemote { create_then_fail: true, ..FlakyRemote::failing(vec![transient()]) };
+        let (calls, created) = (flaky.calls.clone(), flaky.created.clone());
+        created.lock().unwrap().push("root/scuttle.db#0".to_string());
+        let remote = RetryingRemote::new(Box::new(flaky), FAST);
+        let id = remote.upload(Path::new("missing-local-file"), "scuttle.db", Some("root")).await.unwrap();
+        assert_eq!(id, "root/scuttle.db#2");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
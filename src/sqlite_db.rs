@@ -1,8 +1,10 @@
 use anyhow::{Result, Context};
 use rusqlite::{Connection, params};
 use rusqlite::OptionalExtension;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 use chrono::Utc;
+use crate::utils::hashing::{hash_bytes, HashAlgorithm};
 
 pub struct ScuttleDb {
     conn: Connection,
@@ -15,6 +17,94 @@ pub struct TrackedFile {
     pub hash: Option<String>,
     pub last_modified: Option<i64>,
     pub status: Option<String>,
+    pub remote_id: Option<String>,
+    pub size: Option<i64>,
+    /// Stat cache: inode and change time (ns) when the hash was taken, as recorded on this machine.
+    pub inode: Option<i64>,
+    pub ctime: Option<i64>,
+}
+
+/// One entry of a commit, identified by the hash of its content.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub id: String,
+    pub parent: Option<String>,
+    /// Remote head merged in by a pull, if this commit concluded one.
+    pub merge_parent: Option<String>,
+    pub message: String,
+    pub timestamp: i64,
+}
+
+/// A file as recorded in a commit's tree snapshot.
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub path: String,
+    pub hash: String,
+    pub size: Option<i64>,
+    pub last_modified: Option<i64>,
+}
+
+/// Content id of a commit: covers its parents, message, time and full tree, so two
+/// machines agree on the id exactly when they recorded the same commit.
+fn compute_commit_id(parent: Option<&str>, merge_parent: Option<&str>, message: &str, timestamp: i64, tree: &[TreeEntry]) -> String {
+    let mut data = format!(
+        "parent {}\nmerge {}\ntimestamp {}\nmessage {}\n",
+        parent.unwrap_or(""), merge_parent.unwrap_or(""), timestamp, message
+    );
+    for entry in tree {
+        data.push_str(&format!(
+            "{} {} {} {}\n",
+            entry.hash, entry.size.unwrap_or(-1), entry.last_modified.unwrap_or(-1), entry.path
+        ));
+    }
+    hash_bytes(data.as_bytes())
+}
+
+/// Changes since the last sync, classified by which side made them.
+#[derive(Debug, Default)]
+pub struct SyncChanges {
+    /// Changed locally, untouched on the remote.
+    pub local_only: Vec<String>,
+    /// Changed on the remote, untouched locally.
+    pub remote_only: Vec<String>,
+    /// Changed on both sides to different content.
+    pub both_changed: Vec<String>,
+}
+
+/// Map path -> hash for files that are present (rows staged for deletion count as absent).
+fn present_files(files: Vec<TrackedFile>) -> HashMap<String, Option<String>> {
+    files.into_iter()
+        .filter(|f| f.status.as_deref() != Some("deleted"))
+        .map(|f| (f.path, f.hash))
+        .collect()
+}
+
+/// Diff two path -> hash maps. Returns (added, modified, deleted) going from `old` to `new`.
+fn diff_file_maps(old: &HashMap<String, Option<String>>, new: &HashMap<String, Option<String>>) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let mut added = Vec::new();
+    let mut modified = Vec::new();
+    let mut deleted = Vec::new();
+
+    // Added or modified
+    for (path, new_hash_opt) in new {
+        match old.get(path) {
+            None => added.push(path.clone()),
+            Some(old_hash_opt) => {
+                if new_hash_opt.is_some() && new_hash_opt != old_hash_opt {
+                    modified.push(path.clone());
+                }
+            }
+        }
+    }
+
+    // Deleted
+    for path in old.keys() {
+        if !new.contains_key(path) {
+            deleted.push(path.clone());
+        }
+    }
+
+    (added, modified, deleted)
 }
 
 impl ScuttleDb {
@@ -34,55 +124,293 @@ impl ScuttleDb {
                 old_hash TEXT,
                 last_modified INTEGER,
                 status TEXT,
-                existing BOOLEAN DEFAULT 0
+                existing BOOLEAN DEFAULT 0,
+                remote_id TEXT,
+                size INTEGER,
+                inode INTEGER,
+                ctime INTEGER
             );
-            CREATE TABLE IF NOT EXISTS commits (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                message TEXT,
-                timestamp INTEGER,
-                added_files TEXT,
-                updated_files TEXT,
-                deleted_files TEXT
+            CREATE TABLE IF NOT EXISTS remote_folders (
+                path TEXT PRIMARY KEY,
+                remote_id TEXT NOT NULL
             );
-            CREATE TABLE IF NOT EXISTS commit_files (
-                commit_id INTEGER,
-                file_id INTEGER,
-                status TEXT,
-                FOREIGN KEY(commit_id) REFERENCES commits(id),
-                FOREIGN KEY(file_id) REFERENCES files(id)
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT
+            );
+            CREATE TABLE IF NOT EXISTS commit_log (
+                id TEXT PRIMARY KEY,
+                parent TEXT,
+                merge_parent TEXT,
+                message TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS commit_changes (
+                commit_id TEXT NOT NULL REFERENCES commit_log(id),
+                path TEXT NOT NULL,
+                status TEXT NOT NULL,
+                PRIMARY KEY (commit_id, path)
+            );
+            CREATE TABLE IF NOT EXISTS commit_trees (
+                commit_id TEXT NOT NULL REFERENCES commit_log(id),
+                path TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                size INTEGER,
+                last_modified INTEGER,
+                PRIMARY KEY (commit_id, path)
             );"
         ).context("Failed to create tables")?;
+
+        // Columns added after the first release; older DBs get them on open
+        self.add_column_if_missing("files", "remote_id", "TEXT")?;
+        self.add_column_if_missing("files", "size", "INTEGER")?;
+        self.add_column_if_missing("files", "inode", "INTEGER")?;
+        self.add_column_if_missing("files", "ctime", "INTEGER")?;
+        self.migrate_legacy_commits()
+    }
+
+    /// Copy the commits of the first release (`commits`/`commit_files`) into
+    /// `commit_log`/`commit_changes`, once. Those commits never recorded a tree;
+    /// the newest gets one rebuilt from the committed state in `files`, unless
+    /// newer commits were already made on top of it.
+    fn migrate_legacy_commits(&self) -> Result<()> {
+        let legacy_table: Option<i64> = self.conn
+            .query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'commits'", [], |row| row.get(0))
+            .optional()?;
+        if legacy_table.is_none() || self.get_meta("legacy_commits_migrated")?.is_some() {
+            return Ok(());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT message, timestamp, added_files, updated_files, deleted_files FROM commits ORDER BY id"
+        )?;
+        let legacy = stmt.query_map([], |row| {
+            let message: Option<String> = row.get(0)?;
+            let timestamp: Option<i64> = row.get(1)?;
+            let mut changes = Vec::new();
+            for (column, status) in [(2, "added"), (3, "modified"), (4, "deleted")] {
+                let paths: Option<String> = row.get(column)?;
+                for path in paths.unwrap_or_default().split(',').filter(|p| !p.is_empty()) {
+                    changes.push((path.to_string(), status));
+                }
+            }
+            Ok((message.unwrap_or_default(), timestamp.unwrap_or(0), changes))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let head = self.head()?;
+        let tx = self.conn.unchecked_transaction()?;
+        let mut parent: Option<String> = None;
+        for (i, (message, timestamp, changes)) in legacy.iter().enumerate() {
+            let tree = if i + 1 == legacy.len() && head.is_none() {
+                self.committed_tree()?
+            } else {
+                Vec::new()
+            };
+            let commit_id = compute_commit_id(parent.as_deref(), None, message, *timestamp, &tree);
+            tx.execute(
+                "INSERT OR IGNORE INTO commit_log (id, parent, merge_parent, message, timestamp) VALUES (?1, ?2, NULL, ?3, ?4)",
+                params![commit_id, parent, message, timestamp],
+            ).context("Failed to migrate commit")?;
+            for (path, status) in changes {
+                tx.execute(
+                    "INSERT OR REPLACE INTO commit_changes (commit_id, path, status) VALUES (?1, ?2, ?3)",
+                    params![commit_id, path, status],
+                )?;
+            }
+            for entry in &tree {
+                tx.execute(
+                    "INSERT OR IGNORE INTO commit_trees (commit_id, path, hash, size, last_modified) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![commit_id, entry.path, entry.hash, entry.size, entry.last_modified],
+                )?;
+            }
+            parent = Some(commit_id);
+        }
+        if let Some(last) = &parent {
+            // Without newer commits the migrated ones are the history; otherwise `log` shows them separately
+            let key = if head.is_none() { "head" } else { "legacy_head" };
+            tx.execute("INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = ?2", params![key, last])?;
+        }
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES ('legacy_commits_migrated', ?1)",
+            params![legacy.len().to_string()],
+        )?;
+        tx.commit().context("Failed to migrate legacy commits")
+    }
+
+    /// Files as of the last commit: the committed hash of every row, or the hash
+    /// a staged row replaces.
+    fn committed_tree(&self) -> Result<Vec<TreeEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, hash, size, last_modified FROM files WHERE status = 'committed' AND hash IS NOT NULL AND hash != ''
+             UNION ALL
+             SELECT path, old_hash, NULL, NULL FROM files WHERE status != 'committed' AND old_hash IS NOT NULL AND old_hash != ''
+             ORDER BY path"
+        )?;
+        let tree = stmt.query_map([], |row| {
+            Ok(TreeEntry {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                last_modified: row.get(3)?,
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tree)
+    }
+
+    fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt.query_map([], |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .iter()
+            .any(|name| name == column);
+        if !exists {
+            self.conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl), [])
+                .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+        }
         Ok(())
     }
 
-    pub fn add_file(&self, path: &str, hash: &str, last_modified: i64, status: &str) -> Result<()> {
-        // Get current hash to set old_hash
-        let mut stmt = self.conn.prepare("SELECT hash FROM files WHERE path = ?1")?;
-        let old_hash: Option<String> = stmt.query_row(params![path], |row| row.get(0)).optional()?;
+    pub fn add_file(&self, path: &str, hash: &str, size: i64, last_modified: i64, status: &str) -> Result<()> {
+        // The committed hash becomes old_hash; a row staged before keeps the one it has,
+        // so re-staging does not lose what the change is recorded against
+        let mut stmt = self.conn.prepare("SELECT CASE WHEN status = 'committed' THEN hash ELSE old_hash END FROM files WHERE path = ?1")?;
+        let old_hash: Option<String> = stmt.query_row(params![path], |row| row.get(0)).optional()?.flatten();
 
         // Insert if not exists
         self.conn.execute(
-            "INSERT OR IGNORE INTO files (path, hash, old_hash, last_modified, status, existing) VALUES (?1, ?2, NULL, ?3, ?4, 1)",
-            params![path, hash, last_modified, status],
+            "INSERT OR IGNORE INTO files (path, hash, old_hash, last_modified, status, existing, size) VALUES (?1, ?2, NULL, ?3, ?4, 1, ?5)",
+            params![path, hash, last_modified, status, size],
         ).context("Failed to insert file if not exists")?;
 
-        // Update existing record with old_hash set to previous hash
         self.conn.execute(
-            "UPDATE files SET old_hash = ?1, hash = ?2, last_modified = ?3, status = ?4 WHERE path = ?5",
-            params![old_hash, hash, last_modified, status, path],
+            "UPDATE files SET old_hash = ?1, hash = ?2, last_modified = ?3, status = ?4, size = ?5 WHERE path = ?6",
+            params![old_hash, hash, last_modified, status, size, path],
         ).context("Failed to update file")?;
 
         Ok(())
     }
 
+    /// Record a file exactly as another DB has it, already committed (used when pulling).
+    pub fn set_committed_file(&self, file: &TrackedFile) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO files (path, hash, old_hash, last_modified, status, existing, remote_id, size) VALUES (?1, ?2, ?2, ?3, 'committed', 1, ?4, ?5)
+             ON CONFLICT(path) DO UPDATE SET hash = ?2, old_hash = ?2, last_modified = ?3, status = 'committed', remote_id = ?4, size = ?5,
+                 inode = NULL, ctime = NULL",
+            params![file.path, file.hash, file.last_modified, file.remote_id, file.size],
+        ).context("Failed to record pulled file")?;
+        Ok(())
+    }
+
+    /// Record the stat of a file whose content matches its stored hash, so later
+    /// runs can skip re-hashing it while the stat stays the same.
+    pub fn set_stat(&self, path: &str, size: i64, last_modified: i64, inode: Option<i64>, ctime: Option<i64>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET size = ?1, last_modified = ?2, inode = ?3, ctime = ?4 WHERE path = ?5",
+            params![size, last_modified, inode, ctime, path],
+        ).context("Failed to update stat cache")?;
+        Ok(())
+    }
+
+    /// Algorithm of the file hashes in this DB. DBs that never recorded one use SHA-256.
+    pub fn hash_algorithm(&self) -> Result<HashAlgorithm> {
+        match self.get_meta("hash_algorithm")? {
+            None => Ok(HashAlgorithm::Sha256),
+            Some(name) => HashAlgorithm::from_name(&name)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported hash algorithm in DB: {}", name)),
+        }
+    }
+
+    pub fn set_hash_algorithm(&self, algorithm: HashAlgorithm) -> Result<()> {
+        self.set_meta("hash_algorithm", algorithm.as_str())
+    }
+
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        self.conn.query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .context("Failed to read meta value")
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = ?2",
+            params![key, value],
+        ).context("Failed to write meta value")?;
+        Ok(())
+    }
+
+    /// Recorded remote ids only make sense for one remote root. Forget them when
+    /// this DB is about to be synced with a different one.
+    pub fn use_remote_root(&self, root_id: &str) -> Result<()> {
+        if self.get_meta("remote_root")?.as_deref() != Some(root_id) {
+            self.conn.execute_batch("UPDATE files SET remote_id = NULL; DELETE FROM remote_folders;")
+                .context("Failed to reset remote ids")?;
+            self.set_meta("remote_root", root_id)?;
+        }
+        Ok(())
+    }
+
+    /// Map path -> remote id, if the ids in this DB were recorded for `root_id`.
+    pub fn remote_ids(&self, root_id: &str) -> Result<HashMap<String, String>> {
+        if self.get_meta("remote_root")?.as_deref() != Some(root_id) {
+            return Ok(HashMap::new());
+        }
+        Ok(self.get_tracked_files()?
+            .into_iter()
+            .filter_map(|f| f.remote_id.map(|id| (f.path, id)))
+            .collect())
+    }
+
+    /// Remember the remote id a file was uploaded as.
+    pub fn set_remote_id(&self, path: &str, remote_id: &str) -> Result<()> {
+        self.conn.execute("UPDATE files SET remote_id = ?1 WHERE path = ?2", params![remote_id, path])
+            .context("Failed to record remote id")?;
+        Ok(())
+    }
+
+    pub fn get_remote_folder_id(&self, path: &str) -> Result<Option<String>> {
+        self.conn.query_row("SELECT remote_id FROM remote_folders WHERE path = ?1", params![path], |row| row.get(0))
+            .optional()
+            .context("Failed to look up remote folder id")
+    }
+
+    pub fn set_remote_folder_id(&self, path: &str, remote_id: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO remote_folders (path, remote_id) VALUES (?1, ?2)
+             ON CONFLICT(path) DO UPDATE SET remote_id = ?2",
+            params![path, remote_id],
+        ).context("Failed to record remote folder id")?;
+        Ok(())
+    }
+
+    /// Drop a cached folder id, and those of its subfolders, once it turned out stale.
+    pub fn clear_remote_folder_id(&self, path: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM remote_folders WHERE path = ?1 OR path LIKE ?1 || '/%'",
+            params![path],
+        ).context("Failed to clear remote folder id")?;
+        Ok(())
+    }
+
+    /// Forget a file entirely (used when pulling a remote deletion).
+    pub fn remove_file(&self, path: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM files WHERE path = ?1", params![path])
+            .context("Failed to remove file")?;
+        Ok(())
+    }
+
     pub fn get_tracked_files(&self) -> Result<Vec<TrackedFile>> {
-        let mut stmt = self.conn.prepare("SELECT path, hash, last_modified, status FROM files")?;
+        let mut stmt = self.conn.prepare("SELECT path, hash, last_modified, status, remote_id, size, inode, ctime FROM files")?;
         let file_iter = stmt.query_map([], |row| {
             Ok(TrackedFile {
                 path: row.get(0)?,
                 hash: row.get(1)?,
                 last_modified: row.get(2)?,
                 status: row.get(3)?,
+                remote_id: row.get(4)?,
+                size: row.get(5)?,
+                inode: row.get(6)?,
+                ctime: row.get(7)?,
             })
         })?;
 
@@ -104,196 +432,338 @@ impl ScuttleDb {
         let remote_db = ScuttleDb::new(remote_db_path)?;
         let local_db = ScuttleDb::new(local_db_path)?;
 
-        let remote_files = remote_db.get_tracked_files()?;
-        let local_files = local_db.get_tracked_files()?;
-
-        let mut remote_map = std::collections::HashMap::new();
-        for f in remote_files {
-            remote_map.insert(f.path.clone(), f.hash.clone());
-        }
+        let remote_map = present_files(remote_db.get_tracked_files()?);
+        let local_map = present_files(local_db.get_tracked_files()?);
 
-        let mut local_map = std::collections::HashMap::new();
-        for f in local_files {
-            local_map.insert(f.path.clone(), f.hash.clone());
-        }
+        Ok(diff_file_maps(&remote_map, &local_map))
+    }
 
-        let mut added = Vec::new();
-        let mut modified = Vec::new();
-        let mut deleted = Vec::new();
-
-        // Added or modified
-        for (path, local_hash_opt) in &local_map {
-            match remote_map.get(path) {
-                None => added.push(path.clone()),
-                Some(remote_hash_opt) => {
-                    if local_hash_opt.is_some() && remote_hash_opt.is_some() {
-                        if local_hash_opt != remote_hash_opt {
-                            modified.push(path.clone());
-                        }
-                    } else if local_hash_opt.is_some() && remote_hash_opt.is_none() {
-                        modified.push(path.clone());
-                    }
-                }
+    /// Three-way classification of local and remote changes against the DB both
+    /// sides had at the last sync. Without a base, every difference counts.
+    pub fn classify_changes(base_db_path: Option<&Path>, local_db_path: &Path, remote_db_path: &Path) -> Result<SyncChanges> {
+        let base_map = match base_db_path {
+            Some(path) => present_files(ScuttleDb::load_tracked_files(path)?),
+            None => HashMap::new(),
+        };
+        let local_map = present_files(ScuttleDb::load_tracked_files(local_db_path)?);
+        let remote_map = present_files(ScuttleDb::load_tracked_files(remote_db_path)?);
+
+        let changed = |side: &HashMap<String, Option<String>>| -> BTreeSet<String> {
+            let (added, modified, deleted) = diff_file_maps(&base_map, side);
+            added.into_iter().chain(modified).chain(deleted).collect()
+        };
+        let local_changed = changed(&local_map);
+        let remote_changed = changed(&remote_map);
+
+        let mut changes = SyncChanges::default();
+        for path in local_changed.union(&remote_changed) {
+            match (local_changed.contains(path), remote_changed.contains(path)) {
+                (true, false) => changes.local_only.push(path.clone()),
+                (false, true) => changes.remote_only.push(path.clone()),
+                // Both sides ending up with the same content is not a conflict
+                _ if local_map.get(path) == remote_map.get(path) => {}
+                _ => changes.both_changed.push(path.clone()),
             }
         }
+        Ok(changes)
+    }
 
-        // Deleted
-        for (path, _) in &remote_map {
-            if !local_map.contains_key(path) {
-                deleted.push(path.clone());
+    /// Staged rows compared with the tree of the head commit: path -> "added",
+    /// "modified" or "deleted". Rows staged with the content the head commit has
+    /// are no change, however often they were staged in between.
+    fn changes_since_head(&self) -> Result<Vec<(String, &'static str)>> {
+        let committed: HashMap<String, String> = match self.head()? {
+            Some(head) => self.commit_tree(&head)?.into_iter().map(|entry| (entry.path, entry.hash)).collect(),
+            None => HashMap::new(),
+        };
+        let mut changes = Vec::new();
+        for file in self.get_tracked_files()? {
+            if file.status.as_deref() == Some("committed") {
+                continue;
             }
+            let hash = file.hash.as_deref().filter(|h| !h.is_empty());
+            let change = match (file.status.as_deref(), hash, committed.get(&file.path)) {
+                (Some("deleted"), _, Some(_)) => "deleted",
+                (Some("deleted"), _, None) | (_, None, _) => continue,
+                (_, Some(_), None) => "added",
+                (_, Some(hash), Some(old)) if hash != old => "modified",
+                _ => continue,
+            };
+            changes.push((file.path, change));
         }
-
-        Ok((added, modified, deleted))
+        changes.sort();
+        Ok(changes)
     }
 
-    pub fn commit(&self, message: &str) -> Result<()> {
+    /// Record the pending changes as a new commit on top of `head` and return its id.
+    /// A pending merge (see `set_merge_head`) becomes the commit's second parent.
+    pub fn commit(&self, message: &str) -> Result<String> {
         let timestamp = Utc::now().timestamp();
 
-        // Get current tracked files
-        let current_files = self.get_tracked_files()?;
+        let changes = self.changes_since_head()?;
 
-        // Map current files by path to old_hash
-        let mut current_files_map = std::collections::HashMap::new();
-        for file in &current_files {
-            current_files_map.insert(file.path.clone(), file.hash.clone());
+        let tx = self.conn.unchecked_transaction()?;
+
+        // Update files table: staged rows become committed, deleted rows go
+        tx.execute("DELETE FROM files WHERE status = 'deleted'", [])?;
+        tx.execute("UPDATE files SET status = 'committed' WHERE status IS NOT 'committed'", [])?;
+
+        // Snapshot the full tree as of this commit
+        let mut stmt = tx.prepare(
+            "SELECT path, hash, size, last_modified FROM files WHERE hash IS NOT NULL AND status != 'deleted' ORDER BY path"
+        )?;
+        let tree = stmt.query_map([], |row| {
+            Ok(TreeEntry {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                last_modified: row.get(3)?,
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let parent = self.head()?;
+        let merge_parent = self.get_meta("merge_head")?;
+        let commit_id = compute_commit_id(parent.as_deref(), merge_parent.as_deref(), message, timestamp, &tree);
+
+        tx.execute(
+            "INSERT INTO commit_log (id, parent, merge_parent, message, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![commit_id, parent, merge_parent, message, timestamp],
+        ).context("Failed to record commit")?;
+        for (path, status) in &changes {
+            tx.execute(
+                "INSERT OR REPLACE INTO commit_changes (commit_id, path, status) VALUES (?1, ?2, ?3)",
+                params![commit_id, path, status],
+            )?;
+        }
+        for entry in &tree {
+            tx.execute(
+                "INSERT INTO commit_trees (commit_id, path, hash, size, last_modified) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![commit_id, entry.path, entry.hash, entry.size, entry.last_modified],
+            )?;
         }
+        tx.execute("INSERT INTO meta (key, value) VALUES ('head', ?1) ON CONFLICT(key) DO UPDATE SET value = ?1", params![commit_id])?;
+        tx.execute("DELETE FROM meta WHERE key = 'merge_head'", [])?;
+        tx.commit().context("Failed to commit")?;
 
-        // Get last commit files and hashes from files table old_hash
-        let mut stmt = self.conn.prepare("SELECT path, old_hash FROM files")?;
-        let rows = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
-        })?;
+        Ok(commit_id)
+    }
+
+    /// Id of the latest commit, if any.
+    pub fn head(&self) -> Result<Option<String>> {
+        self.get_meta("head")
+    }
+
+    /// Newest commit migrated from the first release, if later commits do not
+    /// build on it (see `migrate_legacy_commits`).
+    pub fn legacy_head(&self) -> Result<Option<String>> {
+        self.get_meta("legacy_head")
+    }
+
+    /// Make the next commit a merge with `commit_id` (the remote head a pull merged).
+    pub fn set_merge_head(&self, commit_id: &str) -> Result<()> {
+        self.set_meta("merge_head", commit_id)
+    }
+
+    pub fn has_commit(&self, commit_id: &str) -> Result<bool> {
+        let found: Option<i64> = self.conn
+            .query_row("SELECT 1 FROM commit_log WHERE id = ?1", params![commit_id], |row| row.get(0))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    pub fn get_commit(&self, commit_id: &str) -> Result<Option<Commit>> {
+        self.conn.query_row(
+            "SELECT id, parent, merge_parent, message, timestamp FROM commit_log WHERE id = ?1",
+            params![commit_id],
+            |row| Ok(Commit {
+                id: row.get(0)?,
+                parent: row.get(1)?,
+                merge_parent: row.get(2)?,
+                message: row.get(3)?,
+                timestamp: row.get(4)?,
+            }),
+        ).optional().context("Failed to read commit")
+    }
 
-        let mut last_files_map = std::collections::HashMap::new();
-        for row in rows {
-            let (path, old_hash) = row?;
-            last_files_map.insert(path, old_hash);
+    /// Resolve `HEAD` or a unique prefix of a commit id to the full id.
+    pub fn resolve_commit(&self, rev: &str) -> Result<String> {
+        if rev.eq_ignore_ascii_case("head") {
+            return self.head()?.ok_or_else(|| anyhow::anyhow!("No commits yet"));
         }
+        let mut stmt = self.conn.prepare("SELECT id FROM commit_log WHERE id LIKE ?1 || '%' LIMIT 2")?;
+        let ids = stmt.query_map(params![rev.to_lowercase()], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        match ids.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => Err(anyhow::anyhow!("Unknown commit: {}", rev)),
+            _ => Err(anyhow::anyhow!("Ambiguous commit id: {}", rev)),
+        }
+    }
 
-        // Determine file changes
-        // status can be "added", "modified", "deleted"
-        let mut changes: Vec<(TrackedFile, &str)> = Vec::new();
+    /// The files as they were at `commit_id`.
+    pub fn commit_tree(&self, commit_id: &str) -> Result<Vec<TreeEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, hash, size, last_modified FROM commit_trees WHERE commit_id = ?1 ORDER BY path"
+        )?;
+        let tree = stmt.query_map(params![commit_id], |row| {
+            Ok(TreeEntry {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                last_modified: row.get(3)?,
+            })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(tree)
+    }
 
-        // Check for added or modified files by comparing current hash with old_hash
-        for file in &current_files {
-            // Skip files with status 'committed'
-            if let Some(status) = &file.status {
-                if status == "committed" {
-                    continue;
-                }
-            }
+    /// Paths changed by `commit_id`, with "added", "modified" or "deleted".
+    pub fn commit_changes(&self, commit_id: &str) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare("SELECT path, status FROM commit_changes WHERE commit_id = ?1 ORDER BY path")?;
+        let changes = stmt.query_map(params![commit_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(changes)
+    }
 
-            // If the file has been marked deleted, record it as deleted
-            if let Some(status) = &file.status {
-                if status == "deleted" {
-                    changes.push((file.clone(), "deleted"));
-                    continue;
-                }
+    /// All commits reachable from `commit_id`, newest first.
+    pub fn history(&self, commit_id: &str) -> Result<Vec<Commit>> {
+        let mut pending = vec![commit_id.to_string()];
+        let mut seen = BTreeSet::new();
+        let mut commits = Vec::new();
+        while let Some(id) = pending.pop() {
+            if !seen.insert(id.clone()) {
+                continue;
             }
-
-            match last_files_map.get(&file.path) {
-                Some(last_hash) => {
-                    match last_hash {
-                        Some(old_hash) => {
-                            match &file.hash {
-                                Some(current_hash) => {
-                                    if current_hash != old_hash {
-                                        changes.push((file.clone(), "modified"));
-                                    }
-                                }
-                                None => {
-                                    // No current hash, treat as unchanged
-                                }
-                            }
-                        }
-                        None => {
-                            // No old_hash means new file
-                            changes.push((file.clone(), "added"));
-                        }
-                    }
-                }
-                None => {
-                    // New file
-                    changes.push((file.clone(), "added"));
-                }
+            if let Some(commit) = self.get_commit(&id)? {
+                pending.extend(commit.parent.clone());
+                pending.extend(commit.merge_parent.clone());
+                commits.push(commit);
             }
         }
+        commits.sort_by_key(|c| std::cmp::Reverse(c.timestamp));
+        Ok(commits)
+    }
 
-        // Check for deleted files
-        for (path, _) in &last_files_map {
-            if !current_files.iter().any(|f| &f.path == path) {
-                // Create a dummy TrackedFile for deleted
-                let deleted_file = TrackedFile {
-                    path: path.clone(),
-                    hash: None,
-                    last_modified: None,
-                    status: None,
-                };
-                changes.push((deleted_file, "deleted"));
+    /// Whether `ancestor` is `descendant` or reachable from it through parent links.
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+        let mut pending = vec![descendant.to_string()];
+        let mut seen = BTreeSet::new();
+        while let Some(id) = pending.pop() {
+            if id == ancestor {
+                return Ok(true);
+            }
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if let Some(commit) = self.get_commit(&id)? {
+                pending.extend(commit.parent);
+                pending.extend(commit.merge_parent);
             }
         }
+        Ok(false)
+    }
 
-        // Separate file paths by change type
-        let mut added_paths = Vec::new();
-        let mut updated_paths = Vec::new();
-        let mut deleted_paths = Vec::new();
+    /// Staged changes as the next commit will record them: path -> "added", "modified" or "deleted".
+    pub fn staged_changes(&self) -> Result<Vec<(String, String)>> {
+        Ok(self.changes_since_head()?
+            .into_iter()
+            .map(|(path, change)| (path, change.to_string()))
+            .collect())
+    }
 
-        // Insert commit record with placeholders for file lists
-        self.conn.execute(
-            "INSERT INTO commits (message, timestamp, added_files, updated_files, deleted_files) VALUES (?1, ?2, '', '', '')",
-            params![message, timestamp],
-        )?;
+    /// Copy the commits of another DB that this one does not have yet.
+    pub fn import_history(&self, other_db_path: &Path) -> Result<()> {
+        self.conn.execute("ATTACH DATABASE ?1 AS other", params![other_db_path.to_string_lossy()])
+            .context("Failed to open DB to import history from")?;
+        let result = self.conn.execute_batch(
+            "BEGIN;
+             INSERT OR IGNORE INTO commit_log SELECT id, parent, merge_parent, message, timestamp FROM other.commit_log;
+             INSERT OR IGNORE INTO commit_changes SELECT commit_id, path, status FROM other.commit_changes;
+             INSERT OR IGNORE INTO commit_trees SELECT commit_id, path, hash, size, last_modified FROM other.commit_trees;
+             COMMIT;"
+        ).context("Failed to import commit history");
+        if result.is_err() {
+            self.conn.execute_batch("ROLLBACK").ok();
+        }
+        self.conn.execute("DETACH DATABASE other", [])?;
+        result
+    }
 
-        let commit_id = self.conn.last_insert_rowid();
+    // Additional methods for querying can be added here
+}
 
-        // Insert commit_files entries and update files table
-        for (file, status) in &changes {
-            // For added or modified, update files table and set status to 'committed'
-            if *status != "deleted" {
-                self.conn.execute(
-                    "UPDATE files SET status = 'committed' WHERE path = ?1",
-                    params![file.path],
-                )?;
-            } else {
-                // For deleted, remove from files table
-                self.conn.execute(
-                    "DELETE FROM files WHERE path = ?1",
-                    params![file.path],
-                )?;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Get file id
-            let mut stmt = self.conn.prepare("SELECT id FROM files WHERE path = ?1")?;
-            let file_id: Option<i64> = stmt.query_row(params![file.path], |row| row.get(0)).optional()?;
+    /// A fresh DB in the system temp dir, removed again when dropped.
+    struct TempDb {
+        path: std::path::PathBuf,
+        db: ScuttleDb,
+    }
 
-            // Insert into commit_files
-            self.conn.execute(
-                "INSERT INTO commit_files (commit_id, file_id, status) VALUES (?1, ?2, ?3)",
-                params![commit_id, file_id, status],
-            )?;
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("scuttle-db-{}-{}.db", name, std::process::id()));
+            std::fs::remove_file(&path).ok();
+            let db = ScuttleDb::new(&path).unwrap();
+            TempDb { path, db }
+        }
 
-            // Collect file paths by status
-            match *status {
-                "added" => added_paths.push(file.path.clone()),
-                "modified" => updated_paths.push(file.path.clone()),
-                "deleted" => deleted_paths.push(file.path.clone()),
-                _ => {}
-            }
+        fn stage(&self, path: &str, hash: &str) {
+            self.db.add_file(path, hash, 1, 1, "staged").unwrap();
         }
+    }
 
-        // Update commit record with file path lists
-        let added_str = added_paths.join(",");
-        let updated_str = updated_paths.join(",");
-        let deleted_str = deleted_paths.join(",");
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
 
-        self.conn.execute(
-            "UPDATE commits SET added_files = ?1, updated_files = ?2, deleted_files = ?3 WHERE id = ?4",
-            params![added_str, updated_str, deleted_str, commit_id],
-        )?;
+    #[test]
+    fn file_staged_twice_before_its_first_commit_is_added() {
+        let temp = TempDb::new("add-twice");
+        temp.stage("a.txt", "a1");
+        temp.db.commit("one").unwrap();
+
+        temp.stage("n.txt", "n1");
+        temp.stage("n.txt", "n2");
+        temp.stage("a.txt", "a2");
+        assert_eq!(temp.db.staged_changes().unwrap(), [("a.txt".to_string(), "modified".to_string()), ("n.txt".to_string(), "added".to_string())]);
+        let commit = temp.db.commit("two").unwrap();
+        let changes = temp.db.commit_changes(&commit).unwrap();
+        assert_eq!(changes, [("a.txt".to_string(), "modified".to_string()), ("n.txt".to_string(), "added".to_string())]);
+    }
 
-        Ok(())
+    #[test]
+    fn content_staged_back_to_head_is_no_change() {
+        let temp = TempDb::new("staged-back");
+        temp.stage("a.txt", "a1");
+        temp.db.commit("one").unwrap();
+        temp.stage("a.txt", "a2");
+        assert_eq!(temp.db.staged_changes().unwrap(), [("a.txt".to_string(), "modified".to_string())]);
+        temp.stage("a.txt", "a1");
+        assert!(temp.db.staged_changes().unwrap().is_empty());
     }
 
-    // Additional methods for querying can be added here
+    #[test]
+    fn commit_classifies_against_the_head_tree() {
+        let temp = TempDb::new("against-head");
+        temp.stage("a.txt", "a1");
+        temp.stage("b.txt", "b1");
+        temp.db.commit("one").unwrap();
+
+        // Changed and changed back, removed, and added then removed again
+        temp.stage("a.txt", "a2");
+        temp.stage("a.txt", "a1");
+        temp.db.add_file("b.txt", "", 0, 0, "deleted").unwrap();
+        temp.stage("t.txt", "t1");
+        temp.db.add_file("t.txt", "", 0, 0, "deleted").unwrap();
+        assert_eq!(temp.db.staged_changes().unwrap(), [("b.txt".to_string(), "deleted".to_string())]);
+        let commit = temp.db.commit("two").unwrap();
+        assert_eq!(temp.db.commit_changes(&commit).unwrap(), [("b.txt".to_string(), "deleted".to_string())]);
+        let tree: Vec<String> = temp.db.commit_tree(&commit).unwrap().into_iter().map(|e| e.path).collect();
+        assert_eq!(tree, ["a.txt"]);
+    }
 }
@@ -2,47 +2,71 @@ use anyhow::Result;
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::time::UNIX_EPOCH;
-use crate::utils::hashing::hash_file;
+use crate::config::service::get_global_ignore_path;
+use crate::utils::ignore_rules::IgnoreRules;
+use crate::utils::objects::store_object;
 use crate::ScuttleDb;
+use crate::sqlite_db::TrackedFile;
 
-pub fn load_scuttleignore() -> Result<Vec<String>> {
-    let ignore_file = PathBuf::from(".scuttleignore");
-    if !ignore_file.exists() {
-        return Ok(vec![]);
+/// The parts of a file's metadata the stat cache compares, like git's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub size: i64,
+    pub last_modified: i64,
+    pub inode: Option<i64>,
+    pub ctime: Option<i64>,
+}
+
+pub fn file_stat(path: &Path) -> Result<FileStat> {
+    let metadata = fs::metadata(path)?;
+    let last_modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    #[cfg(unix)]
+    let (inode, ctime) = {
+        use std::os::unix::fs::MetadataExt;
+        (Some(metadata.ino() as i64), Some(metadata.ctime() * 1_000_000_000 + metadata.ctime_nsec()))
+    };
+    #[cfg(not(unix))]
+    let (inode, ctime) = (None, None);
+    Ok(FileStat { size: metadata.len() as i64, last_modified, inode, ctime })
+}
+
+/// The stored hash of `tracked`, if the file's stat still matches what was recorded
+/// when that hash was taken. `None` means the file has to be hashed.
+pub fn cached_hash(tracked: &TrackedFile, stat: &FileStat) -> Option<String> {
+    let recorded = FileStat {
+        size: tracked.size?,
+        last_modified: tracked.last_modified?,
+        inode: tracked.inode,
+        ctime: tracked.ctime,
+    };
+    // Without an inode/ctime on record (e.g. rows pulled from another machine) nothing is trusted
+    if cfg!(unix) && (recorded.inode.is_none() || recorded.ctime.is_none()) {
+        return None;
     }
-    let content = fs::read_to_string(ignore_file)?;
-    let patterns = content.lines()
-        .map(|line| line.trim())
-        .filter(|line| !line.is_empty() && !line.starts_with('#'))
-        .map(|s| s.to_string())
-        .collect();
-    Ok(patterns)
+    (recorded == *stat).then(|| tracked.hash.clone()).flatten()
+}
+
+pub fn load_scuttleignore() -> Result<IgnoreRules> {
+    let global_file = get_global_ignore_path()?;
+    IgnoreRules::load(Path::new("."), Some(&global_file))
 }
 
-pub fn visit_dirs(dir: &Path, ignore_patterns: &[String], files: &mut Vec<PathBuf>) -> Result<()> {
+pub fn visit_dirs(dir: &Path, ignore_rules: &IgnoreRules, files: &mut Vec<PathBuf>) -> Result<()> {
     if dir.ends_with(".scuttle") {
         return Ok(());
     }
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let is_dir = entry.file_type()?.is_dir();
 
-        // Check ignore patterns
-        if ignore_patterns.iter().any(|pattern| {
-            if pattern.ends_with('/') {
-                // Directory pattern
-                path.is_dir() && file_name == pattern.trim_end_matches('/')
-            } else {
-                // File pattern
-                file_name == pattern
-            }
-        }) {
+        // Ignored directories are not descended into, like git
+        if ignore_rules.is_ignored(&path, is_dir)? {
             continue;
         }
 
-        if path.is_dir() {
-            visit_dirs(&path, ignore_patterns, files)?;
+        if is_dir {
+            visit_dirs(&path, ignore_rules, files)?;
         } else {
             files.push(path);
         }
@@ -50,48 +74,43 @@ pub fn visit_dirs(dir: &Path, ignore_patterns: &[String], files: &mut Vec<PathBu
     Ok(())
 }
 
-pub fn add_file_to_db(db: &ScuttleDb, ignore_patterns: &[String], path: &Path) -> anyhow::Result<()> {
+pub fn add_file_to_db(db: &ScuttleDb, ignore_rules: Option<&IgnoreRules>, path: &Path) -> anyhow::Result<()> {
     // If the file doesn't exist, mark it as deleted in the DB
     if !path.exists() {
         // Use empty hash and zero timestamp for deleted files
-        db.add_file(&path.to_string_lossy(), "", 0, "deleted")?;
+        db.add_file(&path.to_string_lossy(), "", 0, 0, "deleted")?;
         println!("Deleted: {}", path.display());
         return Ok(());
     }
 
     // Check if ignored
-    if is_ignored(path, ignore_patterns)? {
+    if let Some(ignore_rules) = ignore_rules
+        && is_ignored(path, ignore_rules)?
+    {
         return Ok(());
     }
 
-    // Get metadata
-    let metadata = fs::metadata(path)?;
-    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    // Stat before hashing, so a change made while hashing shows up as a stat mismatch later
+    let stat = file_stat(path)?;
 
-    // Calculate hash
-    let hash = hash_file(path)?;
+    // Snapshot the content into the object store; the hash is that of the stored copy
+    let (hash, size) = store_object(path, db.hash_algorithm()?)?;
+    stage_stored_file(db, path, &stat, &hash, size)
+}
 
+/// Stage a file whose content is already in the object store as `hash`.
+/// `stat` must have been taken before the content was hashed.
+pub fn stage_stored_file(db: &ScuttleDb, path: &Path, stat: &FileStat, hash: &str, size: u64) -> Result<()> {
     // Insert or update in DB with status 'staged'
-    db.add_file(&path.to_string_lossy(), &hash, modified, "staged")?;
+    let path_str = path.to_string_lossy();
+    db.add_file(&path_str, hash, size as i64, stat.last_modified, "staged")?;
+    if size as i64 == stat.size {
+        db.set_stat(&path_str, stat.size, stat.last_modified, stat.inode, stat.ctime)?;
+    }
     println!("Staged: {}", path.display());
     Ok(())
 }
 
-pub fn is_ignored(path: &Path, ignore_patterns: &[String]) -> anyhow::Result<bool> {
-    let path_str = path.to_string_lossy();
-    for pattern in ignore_patterns {
-        if pattern.ends_with('/') {
-            // Directory pattern: check if path starts with this directory
-            if path_str.starts_with(pattern) {
-                return Ok(true);
-            }
-        } else {
-            // File pattern: check if file name matches
-            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            if file_name == pattern {
-                return Ok(true);
-            }
-        }
-    }
-    Ok(false)
-}
\ No newline at end of file
+pub fn is_ignored(path: &Path, ignore_rules: &IgnoreRules) -> anyhow::Result<bool> {
+    ignore_rules.is_ignored(path, path.is_dir())
+}
@@ -1,11 +1,97 @@
 use sha2::{Sha256, Digest};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
-use anyhow::Result;
+use std::io::{BufReader, Read};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
 
-pub fn hash_file(path: &Path) -> Result<String> {
-    let data = fs::read(path)?;
+/// Read files in chunks of this size rather than loading them whole.
+const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Algorithm used for file content hashes. A repo records its choice in the DB;
+/// repos created before the choice existed use SHA-256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Hash everything `reader` yields, one chunk at a time.
+pub fn hash_reader(mut reader: impl Read, algorithm: HashAlgorithm) -> Result<String> {
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+    }
+}
+
+/// Hash a file without holding more than one chunk of it in memory.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {} for hashing", path.display()))?;
+    hash_reader(BufReader::with_capacity(HASH_CHUNK_SIZE, file), algorithm)
+        .with_context(|| format!("Failed to hash {}", path.display()))
+}
+
+/// Hash many files concurrently on the rayon thread pool. Results are in input order.
+pub fn hash_files(paths: &[PathBuf], algorithm: HashAlgorithm) -> Vec<Result<String>> {
+    paths.par_iter().map(|path| hash_file(path, algorithm)).collect()
+}
+
+pub fn hash_bytes(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
-    hasher.update(&data);
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+/// MD5 of a file, as Google Drive reports it in `md5Checksum`. Only used to
+/// check transfers, never as a content hash.
+pub fn md5_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {} for hashing", path.display()))?;
+    let mut hasher = md5::Md5::new();
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
     Ok(format!("{:x}", hasher.finalize()))
 }
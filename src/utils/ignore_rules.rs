@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const IGNORE_FILE_NAME: &str = ".scuttleignore";
+
+/// The rule that decided whether a path is ignored, as reported by `check-ignore -v`.
+#[derive(Debug, Clone)]
+pub struct IgnoreMatch {
+    /// Where the rule came from: an ignore file, a remote's excludes or `(built-in)`.
+    pub source: String,
+    pub line: usize,
+    pub pattern: String,
+    /// A `!pattern` rule: the path is explicitly not ignored.
+    pub negated: bool,
+}
+
+impl IgnoreMatch {
+    pub fn is_ignored(&self) -> bool {
+        !self.negated
+    }
+
+    /// `<source>:<line>:<pattern>\t<path>`, the line `check-ignore -v` prints for `path`.
+    pub fn describe(&self, path: &Path) -> String {
+        format!("{}:{}:{}\t{}", self.source, self.line, self.pattern, path.display())
+    }
+}
+
+/// One compiled set of rules, plus the rules by line so matches can be reported.
+struct IgnoreFile {
+    source: String,
+    matcher: Gitignore,
+    lines: Vec<(usize, String)>,
+}
+
+impl IgnoreFile {
+    /// Compile the ignore file at `path`, whose patterns are relative to `base`.
+    fn load(path: &Path, base: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let source = path.strip_prefix(".").unwrap_or(path).display().to_string();
+        Self::from_lines(source, base, content.lines())
+    }
+
+    fn from_lines<'a>(source: String, base: &Path, rules: impl Iterator<Item = &'a str>) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(base);
+        let mut lines = Vec::new();
+        for (index, line) in rules.enumerate() {
+            if let Err(err) = builder.add_line(None, line) {
+                println!("Skipping invalid pattern at {}:{}: {}", source, index + 1, err);
+                continue;
+            }
+            lines.push((index + 1, line.trim_end().to_string()));
+        }
+        let matcher = builder.build().with_context(|| format!("Failed to compile {}", source))?;
+        Ok(IgnoreFile { source, matcher, lines })
+    }
+
+    /// Match `rel_path`, given relative to the file's base directory.
+    fn matched(&self, rel_path: &Path, is_dir: bool) -> Option<IgnoreMatch> {
+        let (glob, negated) = match self.matcher.matched(rel_path, is_dir) {
+            Match::None => return None,
+            Match::Ignore(glob) => (glob, false),
+            Match::Whitelist(glob) => (glob, true),
+        };
+        // The last rule wins, so when a pattern repeats the last copy is the one that matched
+        let line = self.lines.iter().rev()
+            .find(|(_, pattern)| pattern == glob.original())
+            .map(|(line, _)| *line)
+            .unwrap_or(0);
+        Some(IgnoreMatch {
+            source: self.source.clone(),
+            line,
+            pattern: glob.original().to_string(),
+            negated,
+        })
+    }
+}
+
+/// Gitignore-compatible matching against `.scuttleignore` files.
+///
+/// Like git, every directory may have its own ignore file whose patterns are
+/// relative to that directory; deeper files take precedence, and nothing
+/// inside an ignored directory can be re-included. Ignore files are read
+/// lazily the first time a path below their directory is checked.
+///
+/// A user-level ignore file applies below all of the repo's files, and
+/// exclude lists (e.g. a remote's) apply on top: a path they exclude stays
+/// excluded whatever the ignore files say.
+pub struct IgnoreRules {
+    root: PathBuf,
+    files: Mutex<HashMap<PathBuf, Option<Arc<IgnoreFile>>>>,
+    global: Option<IgnoreFile>,
+    excludes: Vec<IgnoreFile>,
+}
+
+impl IgnoreRules {
+    /// Rules from the repo's ignore files under `root` and, if it exists, the user-level `global_file`.
+    pub fn load(root: &Path, global_file: Option<&Path>) -> Result<Self> {
+        let global = match global_file {
+            Some(path) if path.is_file() => Some(IgnoreFile::load(path, root)?),
+            _ => None,
+        };
+        let rules = IgnoreRules { root: root.to_path_buf(), files: Mutex::new(HashMap::new()), global, excludes: Vec::new() };
+        // Surface problems with the top-level file right away
+        rules.file_for(Path::new(""))?;
+        Ok(rules)
+    }
+
+    /// Add exclude patterns, relative to the root, that apply on top of the ignore files.
+    pub fn with_excludes(mut self, source: &str, patterns: &[String]) -> Result<Self> {
+        if !patterns.is_empty() {
+            let excludes = IgnoreFile::from_lines(source.to_string(), &self.root, patterns.iter().map(String::as_str))?;
+            self.excludes.push(excludes);
+        }
+        Ok(self)
+    }
+
+    /// The compiled ignore file of `dir` (relative to the root), if it has one.
+    fn file_for(&self, dir: &Path) -> Result<Option<Arc<IgnoreFile>>> {
+        if let Some(cached) = self.files.lock().unwrap().get(dir) {
+            return Ok(cached.clone());
+        }
+        let path = self.root.join(dir).join(IGNORE_FILE_NAME);
+        let file = if path.is_file() {
+            Some(Arc::new(IgnoreFile::load(&path, &self.root.join(dir))?))
+        } else {
+            None
+        };
+        self.files.lock().unwrap().insert(dir.to_path_buf(), file.clone());
+        Ok(file)
+    }
+
+    /// `path` relative to the root, without `.` components.
+    fn relative(&self, path: &Path) -> Result<PathBuf> {
+        let mut path = path.to_path_buf();
+        if path.is_absolute() {
+            let root = fs::canonicalize(&self.root)?;
+            path = path.strip_prefix(&root)
+                .with_context(|| format!("{} is outside the repository", path.display()))?
+                .to_path_buf();
+        }
+        let mut rel = PathBuf::new();
+        for comp in path.components() {
+            match comp {
+                Component::Normal(name) => rel.push(name),
+                Component::CurDir => {}
+                _ => return Err(anyhow::anyhow!("{} is outside the repository", path.display())),
+            }
+        }
+        Ok(rel)
+    }
+
+    /// The ignore file rule deciding `rel_path` itself, ignoring whether a parent directory is ignored.
+    fn decide(&self, rel_path: &Path, is_dir: bool) -> Result<Option<IgnoreMatch>> {
+        for dir in rel_path.ancestors().skip(1) {
+            if let Some(file) = self.file_for(dir)?
+                && let Some(found) = file.matched(rel_path.strip_prefix(dir)?, is_dir)
+            {
+                return Ok(Some(found));
+            }
+        }
+        Ok(self.global.as_ref().and_then(|global| global.matched(rel_path, is_dir)))
+    }
+
+    /// Apply `decide` to `rel` and, since a file inside an ignored directory stays
+    /// ignored whatever its own rules say, to each of its parent directories first.
+    fn matched_with(&self, rel: &Path, is_dir: bool, decide: impl Fn(&Path, bool) -> Result<Option<IgnoreMatch>>) -> Result<Option<IgnoreMatch>> {
+        let mut parents: Vec<&Path> = rel.ancestors().skip(1).filter(|p| !p.as_os_str().is_empty()).collect();
+        parents.reverse();
+        for parent in parents {
+            if let Some(found) = decide(parent, true)?
+                && found.is_ignored()
+            {
+                return Ok(Some(found));
+            }
+        }
+        decide(rel, is_dir)
+    }
+
+    /// The rule that decides whether `path` is ignored, if any rule applies.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Result<Option<IgnoreMatch>> {
+        let rel = self.relative(path)?;
+        if rel.components().next().is_some_and(|c| c.as_os_str() == ".scuttle") {
+            return Ok(Some(IgnoreMatch { source: "(built-in)".to_string(), line: 0, pattern: ".scuttle".to_string(), negated: false }));
+        }
+        let found = self.matched_with(&rel, is_dir, |p, d| self.decide(p, d))?;
+        if found.as_ref().is_some_and(IgnoreMatch::is_ignored) {
+            return Ok(found);
+        }
+        for excludes in &self.excludes {
+            if let Some(excluded) = self.matched_with(&rel, is_dir, |p, d| Ok(excludes.matched(p, d)))?
+                && excluded.is_ignored()
+            {
+                return Ok(Some(excluded));
+            }
+        }
+        Ok(found)
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> Result<bool> {
+        Ok(self.matched(path, is_dir)?.is_some_and(|m| m.is_ignored()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, holding `files` (path, content).
+    fn temp_tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("scuttle-ignore-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&root).ok();
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn source_of(rules: &IgnoreRules, path: &str) -> Option<(String, usize, bool)> {
+        rules.matched(Path::new(path), false).unwrap().map(|m| (m.source, m.line, m.negated))
+    }
+
+    #[test]
+    fn nested_ignore_files_take_precedence() {
+        let root = temp_tree("nested", &[
+            (".scuttleignore", "*.log\n"),
+            ("sub/.scuttleignore", "# keep this one\n!keep.log\n"),
+        ]);
+        let rules = IgnoreRules::load(&root, None).unwrap();
+
+        assert!(rules.is_ignored(Path::new("keep.log"), false).unwrap());
+        assert!(rules.is_ignored(Path::new("sub/other.log"), false).unwrap());
+        assert!(!rules.is_ignored(Path::new("sub/keep.log"), false).unwrap());
+
+        let (source, line, negated) = source_of(&rules, "sub/keep.log").unwrap();
+        assert!(source.ends_with("sub/.scuttleignore"), "{}", source);
+        assert_eq!((line, negated), (2, true));
+        let (source, line, _) = source_of(&rules, "sub/other.log").unwrap();
+        assert!(!source.ends_with("sub/.scuttleignore") && source.ends_with(".scuttleignore"), "{}", source);
+        assert_eq!(line, 1);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn negation_cannot_reinclude_inside_ignored_directory() {
+        let root = temp_tree("negation", &[
+            (".scuttleignore", "build/\n!build/keep.txt\n*.tmp\n!important.tmp\n"),
+        ]);
+        let rules = IgnoreRules::load(&root, None).unwrap();
+
+        assert!(rules.is_ignored(Path::new("a.tmp"), false).unwrap());
+        assert!(!rules.is_ignored(Path::new("important.tmp"), false).unwrap());
+        assert_eq!(source_of(&rules, "important.tmp").map(|(_, line, negated)| (line, negated)), Some((4, true)));
+        assert!(rules.is_ignored(Path::new("build/keep.txt"), false).unwrap());
+        let found = rules.matched(Path::new("build/keep.txt"), false).unwrap().unwrap();
+        assert_eq!((found.line, found.pattern.as_str()), (1, "build/"));
+        assert!(rules.matched(Path::new("notes.txt"), false).unwrap().is_none());
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn global_file_applies_below_repo_files() {
+        let root = temp_tree("global", &[
+            (".scuttleignore", "!mine.bak\n"),
+            ("global/ignore", "secret.txt\n*.bak\n"),
+        ]);
+        let global = root.join("global/ignore");
+        let rules = IgnoreRules::load(&root, Some(&global)).unwrap();
+
+        assert!(rules.is_ignored(Path::new("secret.txt"), false).unwrap());
+        assert!(rules.is_ignored(Path::new("old.bak"), false).unwrap());
+        assert!(!rules.is_ignored(Path::new("mine.bak"), false).unwrap());
+        let (source, line, _) = source_of(&rules, "old.bak").unwrap();
+        assert!(source.ends_with("global/ignore"), "{}", source);
+        assert_eq!(line, 2);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn remote_excludes_override_ignore_files() {
+        let root = temp_tree("excludes", &[(".scuttleignore", "!disk.iso\n")]);
+        let excludes = vec!["*.iso".to_string(), "big/".to_string()];
+        let rules = IgnoreRules::load(&root, None).unwrap().with_excludes("remote nas", &excludes).unwrap();
+
+        assert!(rules.is_ignored(Path::new("disk.iso"), false).unwrap());
+        assert!(rules.is_ignored(Path::new("big/part1"), false).unwrap());
+        assert!(!rules.is_ignored(Path::new("notes.txt"), false).unwrap());
+        let found = rules.matched(Path::new("disk.iso"), false).unwrap().unwrap();
+        assert_eq!(found.describe(Path::new("disk.iso")), "remote nas:1:*.iso\tdisk.iso");
+        let found = rules.matched(Path::new("big/part1"), false).unwrap().unwrap();
+        assert_eq!(found.describe(Path::new("big/part1")), "remote nas:2:big/\tbig/part1");
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn scuttle_dir_is_always_ignored() {
+        let root = temp_tree("builtin", &[(".scuttleignore", "!.scuttle\n")]);
+        let rules = IgnoreRules::load(&root, None).unwrap();
+        let found = rules.matched(Path::new(".scuttle/scuttle.db"), false).unwrap().unwrap();
+        assert_eq!(found.describe(Path::new(".scuttle/scuttle.db")), "(built-in):0:.scuttle\t.scuttle/scuttle.db");
+        fs::remove_dir_all(root).ok();
+    }
+}
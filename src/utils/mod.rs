@@ -1,2 +1,5 @@
 pub mod hashing;
-pub mod filesystem;
\ No newline at end of file
+pub mod filesystem;
+pub mod ignore_rules;
+pub mod objects;
+pub mod push_journal;
\ No newline at end of file
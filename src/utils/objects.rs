@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::utils::hashing::{hash_reader, HashAlgorithm};
+
+/// Distinguishes temp files of objects stored concurrently.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Content-addressed store of every version that was added, one file per hash.
+pub fn get_objects_dir() -> PathBuf {
+    PathBuf::from(".scuttle/objects")
+}
+
+pub fn object_path(hash: &str) -> PathBuf {
+    get_objects_dir().join(hash)
+}
+
+/// Reads through `inner`, writing every byte it yields to `copy` as well.
+struct CopyingReader<R, W> {
+    inner: R,
+    copy: W,
+    size: u64,
+}
+
+impl<R: Read, W: Write> Read for CopyingReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.copy.write_all(&buf[..read])?;
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+/// Copy `path` into the object store and return the hash and size of the stored bytes.
+///
+/// The file is read once: it is hashed while being copied to a temp file, which is
+/// then renamed to its hash. So a file that changes while it is being added is
+/// stored under the hash of exactly the bytes that were kept.
+pub fn store_object(path: &Path, algorithm: HashAlgorithm) -> Result<(String, u64)> {
+    let objects_dir = get_objects_dir();
+    fs::create_dir_all(&objects_dir).context("Failed to create object store")?;
+
+    let source = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let tmp = objects_dir.join(format!(".{}.{}.tmp", std::process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let copy = fs::File::create(&tmp).context("Failed to create object in the object store")?;
+    let mut reader = CopyingReader { inner: source, copy, size: 0 };
+    let hash = match hash_reader(&mut reader, algorithm) {
+        Ok(hash) => hash,
+        Err(e) => {
+            fs::remove_file(&tmp).ok();
+            return Err(e).with_context(|| format!("Failed to copy {} into the object store", path.display()));
+        }
+    };
+    let size = reader.size;
+    drop(reader);
+
+    let target = object_path(&hash);
+    if target.exists() {
+        fs::remove_file(&tmp)?;
+    } else {
+        // Objects never change once written
+        let mut permissions = fs::metadata(&tmp)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&tmp, permissions)?;
+        if let Err(e) = fs::rename(&tmp, &target) {
+            // Another thread may have stored the same content meanwhile
+            fs::remove_file(&tmp).ok();
+            if !target.exists() {
+                return Err(e).context("Failed to move object into place");
+            }
+        }
+    }
+    Ok((hash, size))
+}
+
+/// Write the stored content `hash` to `dest`, replacing it atomically.
+pub fn restore_object(hash: &str, dest: &Path) -> Result<()> {
+    let object = object_path(hash);
+    if !object.exists() {
+        return Err(anyhow::anyhow!("Content {} of {} is not in the object store", hash, dest.display()));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let name = dest.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let tmp = dest.with_file_name(format!(".{}.scuttle-tmp", name));
+    // Copy contents only, so the working file does not inherit the object's read-only mode
+    let mut source = fs::File::open(&object)?;
+    let mut target = fs::File::create(&tmp)?;
+    io::copy(&mut source, &mut target).with_context(|| format!("Failed to restore {}", dest.display()))?;
+    fs::rename(&tmp, dest).with_context(|| format!("Failed to move restored {} into place", dest.display()))?;
+    Ok(())
+}
+
+/// Where to read the committed content of `rel_path` from: its snapshot in the
+/// object store if there is one, the working copy otherwise.
+pub fn snapshot_path(rel_path: &str, hash: Option<&str>) -> PathBuf {
+    match hash {
+        Some(hash) if !hash.is_empty() && object_path(hash).exists() => object_path(hash),
+        _ => Path::new(".").join(rel_path),
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Where a push records what it is about to do and what it has done.
+pub const JOURNAL_PATH: &str = ".scuttle/push.journal";
+/// The DB a journaled push uploads once every operation is done.
+pub const JOURNAL_DB_PATH: &str = ".scuttle/push.journal.db";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOp {
+    Delete,
+    Upload,
+    Update,
+}
+
+impl PushOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushOp::Delete => "delete",
+            PushOp::Upload => "upload",
+            PushOp::Update => "update",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(PushOp::Delete),
+            "upload" => Some(PushOp::Upload),
+            "update" => Some(PushOp::Update),
+            _ => None,
+        }
+    }
+}
+
+/// One change a push makes to the remote.
+#[derive(Debug, Clone)]
+pub struct PlannedOp {
+    pub op: PushOp,
+    pub path: String,
+    /// The content the remote has before the push, so `--abort` can put it back.
+    pub old_hash: Option<String>,
+    /// The content the push sends.
+    pub new_hash: Option<String>,
+    /// The remote id to act on; once done, the id the file ended up with.
+    pub remote_id: Option<String>,
+    pub done: bool,
+}
+
+/// Write-ahead journal of a push.
+///
+/// The plan is written before the remote is touched, then one line is appended
+/// and flushed per operation that completes, so after a crash the journal says
+/// exactly which changes reached the remote. `push --continue` applies the rest
+/// and swaps the DB; `push --abort` undoes the completed ones. Undone operations
+/// are appended too, so an interrupted abort can also be run again.
+pub struct PushJournal {
+    pub remote: String,
+    pub root_id: String,
+    pub folder_name: String,
+    /// Hash of the remote DB the plan was computed against; empty on a first push.
+    pub remote_db_hash: String,
+    pub ops: Vec<PlannedOp>,
+}
+
+impl PushJournal {
+    pub fn exists() -> bool {
+        Path::new(JOURNAL_PATH).exists()
+    }
+
+    /// Start a journal for `ops`. Fails if one already exists.
+    pub fn begin(remote: &str, root_id: &str, folder_name: &str, remote_db_hash: &str, ops: Vec<PlannedOp>) -> Result<Self> {
+        let journal = PushJournal {
+            remote: remote.to_string(),
+            root_id: root_id.to_string(),
+            folder_name: folder_name.to_string(),
+            remote_db_hash: remote_db_hash.to_string(),
+            ops,
+        };
+        let operations: Vec<serde_json::Value> = journal.ops.iter()
+            .map(|op| json!({
+                "op": op.op.as_str(),
+                "path": op.path,
+                "old_hash": op.old_hash,
+                "new_hash": op.new_hash,
+                "remote_id": op.remote_id,
+            }))
+            .collect();
+        let header = json!({
+            "remote": journal.remote,
+            "root_id": journal.root_id,
+            "folder_name": journal.folder_name,
+            "remote_db_hash": journal.remote_db_hash,
+            "operations": operations,
+        });
+        let mut file = OpenOptions::new().write(true).create_new(true).open(JOURNAL_PATH)
+            .context("Failed to create push journal")?;
+        writeln!(file, "{}", header)?;
+        file.sync_all().context("Failed to write push journal")?;
+        Ok(journal)
+    }
+
+    /// The journal of an unfinished push, if there is one.
+    pub fn load() -> Result<Option<Self>> {
+        if !Self::exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(JOURNAL_PATH).context("Failed to read push journal")?;
+        Self::parse(&data).map(Some)
+    }
+
+    /// Rebuild a journal from its header line and the progress lines after it.
+    fn parse(data: &str) -> Result<Self> {
+        let mut lines = data.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap_or_default())
+            .context("Push journal is corrupt")?;
+        let field = |name: &str| header[name].as_str().unwrap_or_default().to_string();
+        let text = |value: &serde_json::Value| value.as_str().map(|s| s.to_string());
+        let mut ops = Vec::new();
+        for op in header["operations"].as_array().into_iter().flatten() {
+            ops.push(PlannedOp {
+                op: op["op"].as_str().and_then(PushOp::from_name).context("Push journal is corrupt")?,
+                path: text(&op["path"]).context("Push journal is corrupt")?,
+                old_hash: text(&op["old_hash"]),
+                new_hash: text(&op["new_hash"]),
+                remote_id: text(&op["remote_id"]),
+                done: false,
+            });
+        }
+        for line in lines {
+            // A crash can leave the last line half written; nothing after it was recorded
+            let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+                break;
+            };
+            if let Some(op) = entry["done"].as_u64().and_then(|i| ops.get_mut(i as usize)) {
+                op.done = true;
+                if let Some(id) = text(&entry["remote_id"]) {
+                    op.remote_id = Some(id);
+                }
+            } else if let Some(op) = entry["undone"].as_u64().and_then(|i| ops.get_mut(i as usize)) {
+                op.done = false;
+            }
+        }
+        Ok(PushJournal {
+            remote: field("remote"),
+            root_id: field("root_id"),
+            folder_name: field("folder_name"),
+            remote_db_hash: field("remote_db_hash"),
+            ops,
+        })
+    }
+
+    /// Record that operation `index` reached the remote, leaving the file at `remote_id`.
+    pub fn record_done(&mut self, index: usize, remote_id: Option<&str>) -> Result<()> {
+        self.append(json!({ "done": index, "remote_id": remote_id }))?;
+        let op = &mut self.ops[index];
+        op.done = true;
+        if let Some(id) = remote_id {
+            op.remote_id = Some(id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Record that operation `index` was rolled back.
+    pub fn record_undone(&mut self, index: usize) -> Result<()> {
+        self.append(json!({ "undone": index }))?;
+        self.ops[index].done = false;
+        Ok(())
+    }
+
+    fn append(&self, entry: serde_json::Value) -> Result<()> {
+        let mut file = OpenOptions::new().append(true).open(JOURNAL_PATH)
+            .context("Failed to open push journal")?;
+        writeln!(file, "{}", entry)?;
+        file.sync_data().context("Failed to write push journal")
+    }
+
+    /// Drop the journal and its DB once the push is finished or aborted.
+    pub fn remove() -> Result<()> {
+        fs::remove_file(JOURNAL_DB_PATH).ok();
+        fs::remove_file(JOURNAL_PATH).context("Failed to remove push journal")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> String {
+        let op = |op: &str, path: &str, remote_id: Option<&str>| json!({
+            "op": op, "path": path, "old_hash": "old", "new_hash": "new", "remote_id": remote_id,
+        });
+        json!({
+            "remote": "nas",
+            "root_id": "proj",
+            "folder_name": "proj",
+            "remote_db_hash": "dbhash",
+            "operations": [op("delete", "gone.txt", Some("proj/gone.txt")), op("upload", "new.txt", None), op("update", "a.txt", Some("proj/a.txt"))],
+        }).to_string()
+    }
+
+    #[test]
+    fn replays_done_and_undone_lines() {
+        let data = [
+            header(),
+            json!({ "done": 1, "remote_id": "proj/new.txt" }).to_string(),
+            json!({ "done": 2, "remote_id": "proj/a.txt" }).to_string(),
+            json!({ "undone": 2 }).to_string(),
+        ].join("\n");
+        let journal = PushJournal::parse(&data).unwrap();
+        assert_eq!((journal.remote.as_str(), journal.root_id.as_str(), journal.remote_db_hash.as_str()), ("nas", "proj", "dbhash"));
+        let state: Vec<(PushOp, &str, bool)> = journal.ops.iter().map(|op| (op.op, op.path.as_str(), op.done)).collect();
+        assert_eq!(state, [(PushOp::Delete, "gone.txt", false), (PushOp::Upload, "new.txt", true), (PushOp::Update, "a.txt", false)]);
+        assert_eq!(journal.ops[1].remote_id.as_deref(), Some("proj/new.txt"));
+        assert_eq!(journal.ops[1].old_hash.as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn stops_at_a_torn_last_line() {
+        let data = format!("{}\n{}\n{{\"done\":2,\"remo", header(), json!({ "done": 0, "remote_id": "proj/gone.txt" }));
+        let journal = PushJournal::parse(&data).unwrap();
+        let done: Vec<bool> = journal.ops.iter().map(|op| op.done).collect();
+        assert_eq!(done, [true, false, false]);
+    }
+
+    #[test]
+    fn rejects_a_corrupt_header() {
+        assert!(PushJournal::parse("{\"remote\":").is_err());
+        assert!(PushJournal::parse(&json!({ "operations": [{ "op": "rename", "path": "a" }] }).to_string()).is_err());
+    }
+}
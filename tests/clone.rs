@@ -0,0 +1,30 @@
+//! Cloning a repo from the remote, driven through the binary.
+
+mod common;
+
+use std::fs;
+
+use common::Sandbox;
+
+#[test]
+fn pull_fetches_what_a_partial_clone_missed() {
+    let sandbox = Sandbox::new("partial-clone");
+    sandbox.write("a.txt", "a1");
+    sandbox.write("dir/b.txt", "b1");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "one"]);
+    sandbox.ok(&["push"]);
+
+    // b.txt is gone from the remote while cloning, so its download fails
+    let moved = sandbox.root.join("b.txt.away");
+    fs::rename(sandbox.remote().join("dir/b.txt"), &moved).unwrap();
+    sandbox.fails_in("other", &["clone", "proj"]);
+    assert!(!sandbox.root.join("other/proj/dir/b.txt").exists());
+
+    fs::rename(&moved, sandbox.remote().join("dir/b.txt")).unwrap();
+    let pull = sandbox.ok_in("other/proj", &["pull"]);
+    assert!(!pull.contains("Already up to date."), "{}", pull);
+    assert_eq!(fs::read_to_string(sandbox.root.join("other/proj/dir/b.txt")).unwrap(), "b1");
+    assert!(sandbox.ok_in("other/proj", &["pull"]).contains("Already up to date."));
+    assert!(sandbox.ok_in("other/proj", &["status"]).contains("Nothing to commit"));
+}
@@ -0,0 +1,112 @@
+//! Helpers shared by the tests that drive the binary.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A repo `proj` and a local remote `nas`, with their own config dir.
+pub struct Sandbox {
+    pub root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("scuttle-e2e-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&root).ok();
+        for dir in ["cfg/scuttle", "nas", "proj"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let config = serde_json::json!([{
+            "default": true,
+            "path": root.join("nas"),
+            "remote_name": "nas",
+            "service": "local",
+        }]);
+        fs::write(root.join("cfg/scuttle/config.json"), config.to_string()).unwrap();
+        let sandbox = Sandbox { root };
+        sandbox.ok(&["init"]);
+        sandbox
+    }
+
+    /// Run the binary in `dir`, relative to the sandbox root.
+    pub fn run_in(&self, dir: &str, args: &[&str]) -> Output {
+        let dir = self.root.join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_scuttle"))
+            .args(args)
+            .current_dir(dir)
+            .env("XDG_CONFIG_HOME", self.root.join("cfg"))
+            .env("RUST_BACKTRACE", "0")
+            .output()
+            .unwrap()
+    }
+
+    /// Run a command in `dir` that must succeed; returns its stdout. The binary
+    /// exits with status 0 either way, so failure is told by the error it prints.
+    pub fn ok_in(&self, dir: &str, args: &[&str]) -> String {
+        let out = self.run_in(dir, args);
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(!stderr.contains("Error:"), "scuttle {:?} failed:\n{}{}", args, stdout, stderr);
+        stdout
+    }
+
+    /// Run a command in `dir` that must fail; returns its error output.
+    pub fn fails_in(&self, dir: &str, args: &[&str]) -> String {
+        let out = self.run_in(dir, args);
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        assert!(stderr.contains("Error:"), "scuttle {:?} should have failed:\n{}", args, String::from_utf8_lossy(&out.stdout));
+        stderr
+    }
+
+    /// `ok_in` for the repo `proj`.
+    pub fn ok(&self, args: &[&str]) -> String {
+        self.ok_in("proj", args)
+    }
+
+    /// `fails_in` for the repo `proj`.
+    pub fn fails(&self, args: &[&str]) -> String {
+        self.fails_in("proj", args)
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.root.join("proj").join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// The repo's folder on the remote.
+    pub fn remote(&self) -> PathBuf {
+        self.root.join("nas/proj")
+    }
+
+    pub fn journal_exists(&self) -> bool {
+        self.root.join("proj/.scuttle/push.journal").exists()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+/// Every file under `dir`, by relative path.
+pub fn tree(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let rel = path.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/");
+                files.insert(rel, fs::read(&path).unwrap());
+            }
+        }
+    }
+    files
+}
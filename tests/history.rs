@@ -0,0 +1,62 @@
+//! Staging and committing across checkouts, driven through the binary.
+
+mod common;
+
+use common::Sandbox;
+
+/// The id of the newest commit, from `log`.
+fn head(sandbox: &Sandbox) -> String {
+    let log = sandbox.ok(&["log", "-n", "1"]);
+    log.lines().next().and_then(|l| l.strip_prefix("commit ")).unwrap().to_string()
+}
+
+#[test]
+fn checkout_then_commit_records_every_change() {
+    let sandbox = Sandbox::new("checkout-commit");
+    sandbox.write("a.txt", "a1");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "one"]);
+    let first = head(&sandbox);
+    sandbox.write("a.txt", "a2");
+    sandbox.write("c.txt", "c");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "two"]);
+
+    // Back to the first commit, then overwrite a local edit with it once more
+    sandbox.ok(&["checkout", &first]);
+    sandbox.write("a.txt", "a3");
+    sandbox.ok(&["checkout", &first, "--force", "a.txt"]);
+    let status = sandbox.ok(&["status"]);
+    assert!(status.contains("modified:   a.txt"), "{}", status);
+    assert!(sandbox.ok(&["diff", "--staged"]).contains("-a2"));
+
+    sandbox.ok(&["commit", "-m", "three"]);
+    let log = sandbox.ok(&["log", "-n", "1", "--stat"]);
+    assert!(log.contains("0 added, 1 modified, 1 deleted"), "{}", log);
+    assert!(log.contains("M  a.txt") && log.contains("D  c.txt"), "{}", log);
+    assert!(sandbox.ok(&["status"]).contains("Nothing to commit"));
+}
+
+#[test]
+fn status_compares_staged_files_with_head() {
+    let sandbox = Sandbox::new("status-head");
+    sandbox.write("a.txt", "a1");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "one"]);
+
+    sandbox.write("a.txt", "a2");
+    sandbox.ok(&["add", "a.txt"]);
+    sandbox.write("a.txt", "a1");
+    sandbox.ok(&["add", "a.txt"]);
+    sandbox.write("n.txt", "n1");
+    sandbox.ok(&["add", "n.txt"]);
+    sandbox.write("n.txt", "n2");
+    sandbox.ok(&["add", "n.txt"]);
+
+    let status = sandbox.ok(&["status"]);
+    assert!(status.contains("new file:   n.txt"), "{}", status);
+    assert!(!status.contains("a.txt"), "{}", status);
+    let diff = sandbox.ok(&["diff", "--staged"]);
+    assert!(!diff.contains("a.txt"), "{}", diff);
+    assert!(diff.contains("+n2"), "{}", diff);
+}
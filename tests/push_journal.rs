@@ -0,0 +1,105 @@
+//! Interrupted pushes against a local-directory remote, driven through the binary.
+
+mod common;
+
+use common::{tree, Sandbox};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Commit a first state and push it, then commit changes whose push fails
+/// halfway: a file in the remote's way keeps `sub/` from being created.
+/// Returns the remote as the first push left it.
+fn interrupted_push(sandbox: &Sandbox) -> BTreeMap<String, Vec<u8>> {
+    sandbox.write("a.txt", "a1");
+    sandbox.write("b.txt", "b1");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "one"]);
+    sandbox.ok(&["push"]);
+    let pushed = tree(&sandbox.remote());
+
+    sandbox.write("a.txt", "a2");
+    fs::remove_file(sandbox.root.join("proj/b.txt")).unwrap();
+    sandbox.write("sub/new.txt", "n");
+    sandbox.write("d.txt", "d");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "two"]);
+
+    fs::write(sandbox.remote().join("sub"), "in the way").unwrap();
+    sandbox.fails(&["push"]);
+    fs::remove_file(sandbox.remote().join("sub")).unwrap();
+    assert!(sandbox.journal_exists());
+    pushed
+}
+
+#[test]
+fn push_continue_finishes_an_interrupted_push() {
+    let sandbox = Sandbox::new("continue");
+    interrupted_push(&sandbox);
+    assert!(sandbox.fails(&["push"]).contains("push --continue"));
+
+    sandbox.ok(&["push", "--continue"]);
+    assert!(!sandbox.journal_exists());
+    let remote = tree(&sandbox.remote());
+    assert_eq!(remote["a.txt"], b"a2");
+    assert_eq!(remote["d.txt"], b"d");
+    assert_eq!(remote["sub/new.txt"], b"n");
+    assert!(!remote.contains_key("b.txt"));
+    assert!(sandbox.ok(&["push"]).contains("added: 0, modified: 0, deleted: 0"));
+}
+
+#[test]
+fn push_abort_restores_the_remote() {
+    let sandbox = Sandbox::new("abort");
+    let pushed = interrupted_push(&sandbox);
+    assert_ne!(tree(&sandbox.remote()), pushed);
+
+    sandbox.ok(&["push", "--abort"]);
+    assert!(!sandbox.journal_exists());
+    assert_eq!(tree(&sandbox.remote()), pushed);
+    assert!(sandbox.fails(&["push", "--continue"]).contains("No interrupted push"));
+}
+
+/// Commit files and make the first push to a remote folder with a file in the
+/// way of `sub/`, so the push fails halfway.
+fn interrupted_first_push(sandbox: &Sandbox) {
+    sandbox.write("a.txt", "a1");
+    sandbox.write("sub/new.txt", "n");
+    sandbox.ok(&["add", "."]);
+    sandbox.ok(&["commit", "-m", "one"]);
+
+    fs::create_dir_all(sandbox.remote()).unwrap();
+    fs::write(sandbox.remote().join("sub"), "in the way").unwrap();
+    sandbox.fails(&["push"]);
+    fs::remove_file(sandbox.remote().join("sub")).unwrap();
+    assert!(sandbox.journal_exists());
+    assert!(!sandbox.remote().join(".scuttle/scuttle.db").exists());
+}
+
+#[test]
+fn push_continue_finishes_an_interrupted_first_push() {
+    let sandbox = Sandbox::new("continue-first");
+    interrupted_first_push(&sandbox);
+
+    sandbox.ok(&["push", "--continue"]);
+    assert!(!sandbox.journal_exists());
+    let remote = tree(&sandbox.remote());
+    assert_eq!(remote["a.txt"], b"a1");
+    assert_eq!(remote["sub/new.txt"], b"n");
+    assert!(remote.contains_key(".scuttle/scuttle.db"));
+    assert!(sandbox.ok(&["push"]).contains("added: 0, modified: 0, deleted: 0"));
+}
+
+#[test]
+fn push_abort_undoes_an_interrupted_first_push() {
+    let sandbox = Sandbox::new("abort-first");
+    interrupted_first_push(&sandbox);
+
+    sandbox.ok(&["push", "--abort"]);
+    assert!(!sandbox.journal_exists());
+    assert!(tree(&sandbox.remote()).is_empty());
+
+    sandbox.ok(&["push"]);
+    let remote = tree(&sandbox.remote());
+    assert_eq!(remote["a.txt"], b"a1");
+    assert_eq!(remote["sub/new.txt"], b"n");
+}